@@ -0,0 +1,117 @@
+use std::collections::BTreeSet;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::cmd::call;
+use crate::error::FatalError;
+use crate::Features;
+
+#[derive(Debug, Deserialize)]
+struct Crate {
+    paths: std::collections::HashMap<String, ItemSummary>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ItemSummary {
+    crate_id: u32,
+    path: Vec<String>,
+    kind: String,
+}
+
+/// Generate the rustdoc JSON for a library and collect its public items.
+///
+/// Items are reported as `<kind> <path>`, for example `function mycrate::foo`.  Returns `None`
+/// when rustdoc fails, e.g. because `toolchain` isn't installed.
+pub fn public_api(
+    manifest_path: &Path,
+    lib_name: &str,
+    features: &Features,
+    target_dir: &Path,
+    toolchain: &str,
+) -> Result<Option<BTreeSet<String>>, FatalError> {
+    let mut command: Vec<&str> = vec![
+        "rustup",
+        "run",
+        toolchain,
+        "cargo",
+        "rustdoc",
+        "--lib",
+        "--manifest-path",
+        manifest_path.to_str().unwrap(),
+        "--target-dir",
+        target_dir.to_str().unwrap(),
+    ];
+
+    let feature_arg;
+    match features {
+        Features::None => (),
+        Features::Selective(vec) => {
+            feature_arg = vec.join(" ");
+            command.push("--features");
+            command.push(&feature_arg);
+        }
+        Features::All => {
+            command.push("--all-features");
+        }
+    };
+
+    command.extend(&["--", "-Zunstable-options", "--output-format", "json"]);
+
+    if !call(command, false)? {
+        return Ok(None);
+    }
+
+    let json_path = target_dir
+        .join("doc")
+        .join(format!("{}.json", lib_name.replace('-', "_")));
+    let json = std::fs::read_to_string(json_path)?;
+    parse_public_api(&json).map(Some)
+}
+
+fn parse_public_api(json: &str) -> Result<BTreeSet<String>, FatalError> {
+    let krate: Crate = serde_json::from_str(json)?;
+    let items = krate
+        .paths
+        .values()
+        .filter(|item| item.crate_id == 0)
+        .map(|item| format!("{} {}", item.kind, item.path.join("::")))
+        .collect();
+    Ok(items)
+}
+
+/// Public items of `old` that are missing from `new`.
+pub fn breaking_changes<'a>(old: &'a BTreeSet<String>, new: &'a BTreeSet<String>) -> Vec<&'a str> {
+    old.difference(new).map(|s| s.as_str()).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    static OLD: &str = r#"{
+        "paths": {
+            "0": {"crate_id": 0, "path": ["foo"], "kind": "module"},
+            "1": {"crate_id": 0, "path": ["foo", "bar"], "kind": "function"},
+            "2": {"crate_id": 0, "path": ["foo", "Baz"], "kind": "struct"},
+            "3": {"crate_id": 1, "path": ["std", "string", "String"], "kind": "struct"}
+        }
+    }"#;
+
+    #[test]
+    fn parse() {
+        let api = parse_public_api(OLD).unwrap();
+        let api: Vec<_> = api.iter().map(|s| s.as_str()).collect();
+        assert_eq!(api, ["function foo::bar", "module foo", "struct foo::Baz"]);
+    }
+
+    #[test]
+    fn removed_items_are_breaking() {
+        let old = parse_public_api(OLD).unwrap();
+        let mut new = old.clone();
+        new.remove("function foo::bar");
+        new.insert("function foo::qux".to_owned());
+        assert_eq!(breaking_changes(&old, &new), ["function foo::bar"]);
+        assert!(breaking_changes(&new, &new).is_empty());
+    }
+}
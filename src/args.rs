@@ -0,0 +1,427 @@
+//! Command-line arguments of `cargo release`
+
+use std::path::PathBuf;
+
+use boolinator::Boolinator;
+use structopt::StructOpt;
+
+use crate::config;
+use crate::release;
+
+#[derive(Debug, StructOpt)]
+pub struct ReleaseOpt {
+    #[structopt(subcommand)]
+    pub command: Option<ReleaseCommand>,
+
+    #[structopt(flatten)]
+    pub manifest: clap_cargo::Manifest,
+
+    #[structopt(flatten)]
+    pub workspace: clap_cargo::Workspace,
+
+    /// Release level or version: bumping specified version field or remove prerelease extensions by default. Possible level value: major, minor, patch, release, rc, beta, alpha, snapshot or any valid semver version that is greater than current version
+    #[structopt(case_insensitive(true), default_value = "release")]
+    pub level_or_version: String,
+
+    #[structopt(short = "m")]
+    /// Semver metadata, overrides the `metadata` config
+    pub metadata: Option<String>,
+
+    #[structopt(short = "c", long = "config")]
+    /// Custom config file
+    pub custom_config: Option<String>,
+
+    #[structopt(long)]
+    /// Ignore implicit configuration files.
+    pub isolated: bool,
+
+    #[structopt(long, env = "CARGO_RELEASE_PROFILE")]
+    /// Apply the `[profile.<name>]` tables of the config files
+    pub profile: Option<String>,
+
+    #[structopt(flatten)]
+    pub config: ConfigArgs,
+
+    #[structopt(short = "n", long)]
+    /// Do not actually change anything, just log what are going to do
+    pub dry_run: bool,
+
+    #[structopt(long)]
+    /// Skip release confirmation and version preview
+    pub no_confirm: bool,
+
+    #[structopt(long)]
+    /// The name of tag for the previous release.
+    pub prev_tag_name: Option<String>,
+
+    #[structopt(flatten)]
+    pub logging: Verbosity,
+}
+
+impl ReleaseOpt {
+    pub fn release_options(&self) -> release::ReleaseOptions {
+        let mut config = config::Config::default();
+        config.update(&self.config);
+        release::ReleaseOptions {
+            manifest_path: self.manifest.manifest_path.clone(),
+            packages: self.workspace.package.clone(),
+            workspace: self.workspace.workspace || self.workspace.all,
+            exclude: self.workspace.exclude.clone(),
+            level_or_version: self.level_or_version.clone(),
+            metadata: self.metadata.clone(),
+            custom_config: self.custom_config.as_ref().map(PathBuf::from),
+            isolated: self.isolated,
+            profile: self.profile.clone(),
+            config,
+            token: self.config.token.clone(),
+            dry_run: self.dry_run,
+            no_confirm: self.no_confirm,
+            prev_tag_name: self.prev_tag_name.clone(),
+        }
+    }
+}
+
+#[derive(Debug, StructOpt)]
+#[allow(clippy::large_enum_variant)] // Parsed once, not worth boxing
+pub enum ReleaseCommand {
+    /// Show the effective release configuration
+    #[structopt(name = "config")]
+    Config(ConfigOpt),
+    /// Generate a `release.toml` from the workspace's tags, documentation and crates
+    #[structopt(name = "init")]
+    Init(InitOpt),
+    /// Show what changed since the last release of each crate
+    #[structopt(name = "status")]
+    Status(StatusOpt),
+}
+
+#[derive(Debug, StructOpt)]
+pub struct ConfigOpt {
+    #[structopt(flatten)]
+    pub manifest: clap_cargo::Manifest,
+
+    #[structopt(flatten)]
+    pub workspace: Packages,
+
+    #[structopt(short = "c", long = "config")]
+    /// Custom config file
+    pub custom_config: Option<String>,
+
+    #[structopt(long)]
+    /// Ignore implicit configuration files.
+    pub isolated: bool,
+
+    #[structopt(long, env = "CARGO_RELEASE_PROFILE")]
+    /// Apply the `[profile.<name>]` tables of the config files
+    pub profile: Option<String>,
+
+    #[structopt(flatten)]
+    pub config: ConfigArgs,
+
+    #[structopt(long)]
+    /// Show where each value comes from, including shadowed values
+    pub explain: bool,
+
+    #[structopt(long, conflicts_with = "explain")]
+    /// Validate every config source of the workspace
+    pub check: bool,
+
+    #[structopt(long, conflicts_with_all = &["explain", "check"])]
+    /// Print the JSON Schema of config files
+    pub schema: bool,
+}
+
+/// Crate selection, as `clap_cargo::Workspace` with `-p` for `--package`
+#[derive(Debug, StructOpt)]
+pub struct Packages {
+    #[structopt(short = "p", long)]
+    /// Package to process (see `cargo help pkgid`)
+    pub package: Vec<String>,
+
+    #[structopt(long)]
+    /// Process all packages in the workspace
+    pub workspace: bool,
+
+    #[structopt(long, hidden_short_help(true), hidden_long_help(true))]
+    /// Process all packages in the workspace
+    pub all: bool,
+
+    #[structopt(long)]
+    /// Exclude packages from being processed
+    pub exclude: Vec<String>,
+}
+
+impl Packages {
+    /// Partition workspace members into those selected and those excluded
+    pub fn partition_packages<'m>(
+        &self,
+        meta: &'m cargo_metadata::Metadata,
+    ) -> (
+        Vec<&'m cargo_metadata::Package>,
+        Vec<&'m cargo_metadata::Package>,
+    ) {
+        let mut workspace = clap_cargo::Workspace::default();
+        workspace.package = self.package.clone();
+        workspace.workspace = self.workspace;
+        workspace.all = self.all;
+        workspace.exclude = self.exclude.clone();
+        workspace.partition_packages(meta)
+    }
+}
+
+#[derive(Debug, StructOpt)]
+pub struct InitOpt {
+    #[structopt(flatten)]
+    pub manifest: clap_cargo::Manifest,
+
+    #[structopt(long)]
+    /// Overwrite existing `release.toml` files
+    pub force: bool,
+
+    #[structopt(short = "n", long)]
+    /// Print the generated files instead of writing them
+    pub dry_run: bool,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct StatusOpt {
+    #[structopt(flatten)]
+    pub manifest: clap_cargo::Manifest,
+
+    #[structopt(flatten)]
+    pub workspace: clap_cargo::Workspace,
+
+    #[structopt(short = "c", long = "config")]
+    /// Custom config file
+    pub custom_config: Option<String>,
+
+    #[structopt(long)]
+    /// Ignore implicit configuration files.
+    pub isolated: bool,
+
+    #[structopt(long, env = "CARGO_RELEASE_PROFILE")]
+    /// Apply the `[profile.<name>]` tables of the config files
+    pub profile: Option<String>,
+
+    #[structopt(long)]
+    /// Don't check the crates.io index and the git remote
+    pub offline: bool,
+}
+
+#[derive(StructOpt, Debug, Default, Clone)]
+pub struct Verbosity {
+    /// Pass many times for less log output
+    #[structopt(long, short = "q", parse(from_occurrences))]
+    pub quiet: i8,
+
+    /// Pass many times for more log output
+    ///
+    /// By default, it'll report info. Passing `-v` one time also prints
+    /// warnings, `-vv` enables info logging, `-vvv` debug, and `-vvvv` trace.
+    #[structopt(long, short = "v", parse(from_occurrences))]
+    pub verbose: i8,
+}
+
+impl Verbosity {
+    /// Get the log level.
+    pub fn log_level(&self) -> log::Level {
+        let verbosity = 2 - self.quiet + self.verbose;
+
+        match verbosity {
+            std::i8::MIN..=0 => log::Level::Error,
+            1 => log::Level::Warn,
+            2 => log::Level::Info,
+            3 => log::Level::Debug,
+            4..=std::i8::MAX => log::Level::Trace,
+        }
+    }
+}
+
+#[derive(Debug, Default, StructOpt)]
+pub struct ConfigArgs {
+    #[structopt(long)]
+    /// Sign both git commit and tag,
+    pub sign: bool,
+
+    #[structopt(long)]
+    /// Sign git commit
+    pub sign_commit: bool,
+
+    #[structopt(long)]
+    /// Sign git tag
+    pub sign_tag: bool,
+
+    #[structopt(long)]
+    /// Git remote to push
+    pub push_remote: Option<String>,
+
+    #[structopt(long)]
+    /// Cargo registry to upload to
+    pub registry: Option<String>,
+
+    #[structopt(long)]
+    /// Do not run cargo publish on release
+    pub skip_publish: bool,
+
+    #[structopt(long)]
+    /// Do not run git push in the last step
+    pub skip_push: bool,
+
+    #[structopt(long)]
+    /// Do not create git tag
+    pub skip_tag: bool,
+
+    #[structopt(long)]
+    /// Do not run the `verify-commands` before release
+    pub skip_verify: bool,
+
+    #[structopt(
+        long,
+        possible_values(&config::DependentVersion::variants()),
+        case_insensitive(true),
+    )]
+    /// Specify how workspace dependencies on this crate should be handed.
+    pub dependent_version: Option<config::DependentVersion>,
+
+    #[structopt(
+        long,
+        possible_values(&config::DevDependents::variants()),
+        case_insensitive(true),
+    )]
+    /// Specify how workspace dev-dependencies on this crate should be handled
+    pub dev_dependents: Option<config::DevDependents>,
+
+    #[structopt(long)]
+    /// Prefix of git tag, note that this will override default prefix based on sub-directory
+    pub tag_prefix: Option<String>,
+
+    #[structopt(long)]
+    /// The name of the git tag.
+    pub tag_name: Option<String>,
+
+    #[structopt(
+        long,
+        possible_values(&config::TagKind::variants()),
+        case_insensitive(true),
+    )]
+    /// Create annotated or lightweight git tags
+    pub tag_kind: Option<config::TagKind>,
+
+    #[structopt(long)]
+    /// Pre-release identifier(s) to append to the next development version after release
+    pub dev_version_ext: Option<String>,
+
+    #[structopt(long)]
+    /// Do not create dev version after release
+    pub no_dev_version: bool,
+
+    #[structopt(
+        long,
+        possible_values(&config::DevVersionLevel::variants()),
+        case_insensitive(true),
+    )]
+    /// Version field to bump for the next development version after release
+    pub dev_version_level: Option<config::DevVersionLevel>,
+
+    #[structopt(long)]
+    /// Provide a set of features that need to be enabled
+    pub features: Vec<String>,
+
+    #[structopt(long)]
+    /// Enable all features via `all-features`. Overrides `features`
+    pub all_features: bool,
+
+    #[structopt(long)]
+    /// Refuse breaking public API changes in semver compatible releases (requires nightly)
+    pub check_api: bool,
+
+    #[structopt(long)]
+    /// Token to use when uploading
+    pub token: Option<String>,
+}
+
+impl config::ConfigSource for ConfigArgs {
+    fn sign_commit(&self) -> Option<bool> {
+        self.sign
+            .as_some(true)
+            .or_else(|| self.sign_commit.as_some(true))
+    }
+
+    fn sign_tag(&self) -> Option<bool> {
+        self.sign
+            .as_some(true)
+            .or_else(|| self.sign_tag.as_some(true))
+    }
+
+    fn push_remote(&self) -> Option<&str> {
+        self.push_remote.as_deref()
+    }
+
+    fn registry(&self) -> Option<&str> {
+        self.registry.as_deref()
+    }
+
+    fn disable_publish(&self) -> Option<bool> {
+        self.skip_publish.as_some(true)
+    }
+
+    fn disable_push(&self) -> Option<bool> {
+        self.skip_push.as_some(true)
+    }
+
+    fn dev_version_ext(&self) -> Option<&str> {
+        self.dev_version_ext.as_deref()
+    }
+
+    fn no_dev_version(&self) -> Option<bool> {
+        self.no_dev_version.as_some(true)
+    }
+
+    fn dev_version_level(&self) -> Option<config::DevVersionLevel> {
+        self.dev_version_level
+    }
+
+    fn tag_prefix(&self) -> Option<&str> {
+        self.tag_prefix.as_deref()
+    }
+
+    fn tag_name(&self) -> Option<&str> {
+        self.tag_name.as_deref()
+    }
+
+    fn tag_kind(&self) -> Option<config::TagKind> {
+        self.tag_kind
+    }
+
+    fn disable_tag(&self) -> Option<bool> {
+        self.skip_tag.as_some(true)
+    }
+
+    fn enable_features(&self) -> Option<&[String]> {
+        if !self.features.is_empty() {
+            Some(self.features.as_slice())
+        } else {
+            None
+        }
+    }
+
+    fn enable_all_features(&self) -> Option<bool> {
+        self.all_features.as_some(true)
+    }
+
+    fn dependent_version(&self) -> Option<config::DependentVersion> {
+        self.dependent_version
+    }
+
+    fn dev_dependents(&self) -> Option<config::DevDependents> {
+        self.dev_dependents
+    }
+
+    fn check_api(&self) -> Option<bool> {
+        self.check_api.as_some(true)
+    }
+
+    fn disable_verify(&self) -> Option<bool> {
+        self.skip_verify.as_some(true)
+    }
+}
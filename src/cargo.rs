@@ -1,9 +1,11 @@
+use std::collections::BTreeSet;
 use std::env;
 use std::fs::{self, File};
 use std::io;
 use std::io::prelude::*;
 use std::path::Path;
 
+use sha2::{Digest, Sha256};
 use toml::Value;
 
 use crate::cmd::call;
@@ -71,13 +73,7 @@ pub fn wait_for_publish(
             if let Err(e) = index.update() {
                 log::debug!("Crate index update failed with {}", e);
             }
-            let crate_data = index.crate_(name);
-            let published = crate_data
-                .iter()
-                .flat_map(|c| c.versions().iter())
-                .any(|v| v.version() == version);
-
-            if published {
+            if is_published(&index, name, version) {
                 break;
             } else if timeout < now.elapsed() {
                 return Err(FatalError::PublishTimeoutError);
@@ -94,69 +90,326 @@ pub fn wait_for_publish(
     Ok(())
 }
 
-pub fn set_package_version(manifest_path: &Path, version: &str) -> Result<(), FatalError> {
-    let temp_manifest_path = manifest_path
-        .parent()
-        .unwrap_or_else(|| Path::new("."))
-        .join("Cargo.toml.work");
+/// Whether the crates.io `index` has `version` of `name`
+pub fn is_published(index: &crates_index::Index, name: &str, version: &str) -> bool {
+    let crate_data = index.crate_(name);
+    crate_data
+        .iter()
+        .flat_map(|c| c.versions().iter())
+        .any(|v| v.version() == version)
+}
 
-    {
-        let manifest = load_from_file(manifest_path)?;
-        let mut manifest: toml_edit::Document = manifest.parse().map_err(FatalError::from)?;
-        manifest["package"]["version"] = toml_edit::value(version);
+/// The latest version of `name` on `registry`, from `cargo search`, or `None` if the registry
+/// can't be searched or doesn't have the crate
+pub fn search_version(name: &str, registry: &str) -> Option<String> {
+    let output = std::process::Command::new(cargo())
+        .arg("search")
+        .arg("--registry")
+        .arg(registry)
+        .arg(name)
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        log::debug!(
+            "Searching {} failed with {}",
+            registry,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+        return None;
+    }
+    searched_version(&String::from_utf8_lossy(&output.stdout), name)
+}
 
-        let mut file_out = File::create(&temp_manifest_path).map_err(FatalError::from)?;
-        file_out
-            .write(manifest.to_string_in_original_order().as_bytes())
-            .map_err(FatalError::from)?;
+/// The version of `name` in `cargo search` output, made of lines like `name = "1.0.0"  # ...`
+fn searched_version(output: &str, name: &str) -> Option<String> {
+    let prefix = format!("{} = \"", name);
+    output.lines().find_map(|line| {
+        let rest = line.strip_prefix(prefix.as_str())?;
+        rest.find('"').map(|end| rest[..end].to_owned())
+    })
+}
+
+/// SHA-256 of the `.crate` file of `version` of `name` in the crates.io `index`
+pub fn indexed_checksum(index: &crates_index::Index, name: &str, version: &str) -> Option<String> {
+    let crate_data = index.crate_(name)?;
+    crate_data
+        .versions()
+        .iter()
+        .find(|v| v.version() == version)
+        .map(|v| v.checksum().iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+/// SHA-256 of the `.crate` file `cargo publish` left in `target_dir`, if any
+pub fn crate_checksum(
+    target_dir: &Path,
+    name: &str,
+    version: &str,
+) -> Result<Option<String>, FatalError> {
+    let path = target_dir
+        .join("package")
+        .join(format!("{}-{}.crate", name, version));
+    if !path.exists() {
+        return Ok(None);
     }
-    fs::rename(temp_manifest_path, manifest_path)?;
+    let data = fs::read(&path)?;
+    Ok(Some(format!("{:x}", Sha256::digest(&data))))
+}
 
-    Ok(())
+/// Set the version of the package at `manifest_path`.
+///
+/// With `version.workspace = true`, the `[workspace.package]` version in `workspace_manifest_path`
+/// is updated instead.
+pub fn set_package_version(
+    manifest_path: &Path,
+    workspace_manifest_path: &Path,
+    version: &str,
+) -> Result<(), FatalError> {
+    if is_version_inherited(manifest_path)? {
+        edit_manifest(workspace_manifest_path, |manifest| {
+            manifest["workspace"]["package"]["version"] = toml_edit::value(version);
+            Ok(())
+        })
+    } else {
+        edit_manifest(manifest_path, |manifest| {
+            manifest["package"]["version"] = toml_edit::value(version);
+            Ok(())
+        })
+    }
+}
+
+/// Whether the package at `manifest_path` has `version.workspace = true`
+pub fn is_version_inherited(manifest_path: &Path) -> Result<bool, FatalError> {
+    let manifest = parse_cargo_config(manifest_path)?;
+    Ok(manifest
+        .get("package")
+        .and_then(|p| p.get("version"))
+        .map(is_workspace_inherited)
+        .unwrap_or(false))
+}
+
+/// A dependency table of a manifest, e.g. `[target.'cfg(unix)'.dev-dependencies]`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DependencyTable {
+    /// `dependencies`, `dev-dependencies` or `build-dependencies`
+    pub name: &'static str,
+    /// The `cfg(...)` expression or target triple of a platform-specific table
+    pub target: Option<String>,
+}
+
+impl DependencyTable {
+    /// The table `dep` was declared in
+    pub fn of(dep: &cargo_metadata::Dependency) -> Option<Self> {
+        let name = match dep.kind {
+            cargo_metadata::DependencyKind::Normal => "dependencies",
+            cargo_metadata::DependencyKind::Development => "dev-dependencies",
+            cargo_metadata::DependencyKind::Build => "build-dependencies",
+            _ => return None,
+        };
+        Some(DependencyTable {
+            name,
+            target: dep.target.as_ref().map(|t| t.to_string()),
+        })
+    }
+
+    /// The key of this table's `[target]` entry in `targets`, compared ignoring whitespace as
+    /// cargo normalizes `cfg` expressions
+    fn target_key<'t>(&self, targets: impl Iterator<Item = &'t str>) -> Option<&'t str> {
+        let normalize = |t: &str| t.chars().filter(|c| !c.is_whitespace()).collect::<String>();
+        let target = normalize(self.target.as_deref()?);
+        targets.into_iter().find(|key| normalize(key) == target)
+    }
 }
 
+/// Set the version requirement of the dependency `key` in `table` of `manifest_path`.
+///
+/// When the entry inherits from the workspace (`workspace = true`), its entry in
+/// `[workspace.dependencies]` of `ws_manifest_path` is updated instead.
 pub fn set_dependency_version(
     manifest_path: &Path,
-    name: &str,
+    ws_manifest_path: &Path,
+    table: &DependencyTable,
+    key: &str,
     version: &str,
 ) -> Result<(), FatalError> {
-    let temp_manifest_path = manifest_path
-        .parent()
-        .unwrap_or_else(|| Path::new("."))
-        .join("Cargo.toml.work");
+    update_dependency_version(manifest_path, ws_manifest_path, table, key, version, false)
+}
 
-    {
-        let manifest = load_from_file(manifest_path)?;
-        let mut manifest: toml_edit::Document = manifest.parse().map_err(FatalError::from)?;
+/// Like [`set_dependency_version`] for a registry dependency, e.g. of a downstream repository,
+/// which may be a bare requirement like `foo = "1.0"`
+pub fn set_registry_dependency_version(
+    manifest_path: &Path,
+    ws_manifest_path: &Path,
+    table: &DependencyTable,
+    key: &str,
+    version: &str,
+) -> Result<(), FatalError> {
+    update_dependency_version(manifest_path, ws_manifest_path, table, key, version, true)
+}
 
-        let dep_table_names = &["dependencies", "dev-dependencies", "build-dependencies"];
-        for key in dep_table_names {
-            if let Some(deps_table) = manifest
-                .as_table_mut()
-                .get_mut(key)
+fn update_dependency_version(
+    manifest_path: &Path,
+    ws_manifest_path: &Path,
+    table: &DependencyTable,
+    key: &str,
+    version: &str,
+    registry: bool,
+) -> Result<(), FatalError> {
+    // Only rewrite manifests that need it, `toml_edit` can't parse every manifest `cargo` can
+    let manifest = parse_cargo_config(manifest_path)?;
+    let parent = match table.target {
+        None => Some(&manifest),
+        Some(_) => manifest
+            .get("target")
+            .and_then(|t| t.as_table())
+            .and_then(|targets| {
+                let target_key = table.target_key(targets.keys().map(|k| k.as_str()))?;
+                targets.get(target_key)
+            }),
+    };
+    let dep = parent
+        .and_then(|p| p.get(table.name))
+        .and_then(|deps| deps.get(key));
+    match dep {
+        None => return Ok(()),
+        Some(dep) if is_workspace_inherited(dep) => {
+            return set_workspace_dependency_version(ws_manifest_path, key, version, registry);
+        }
+        Some(_) => {}
+    }
+
+    edit_manifest(manifest_path, |manifest| {
+        let root = manifest.as_table_mut();
+        let parent = match table.target {
+            None => Some(root),
+            Some(_) => root
+                .get_mut("target")
                 .and_then(|i| i.as_table_mut())
-            {
-                set_version(deps_table, name, version)?;
-            }
+                .and_then(|targets| {
+                    let target_key = table.target_key(targets.iter().map(|(k, _)| k))?.to_owned();
+                    targets.get_mut(&target_key).and_then(|i| i.as_table_mut())
+                }),
+        };
+        if let Some(deps_table) = parent
+            .and_then(|p| p.get_mut(table.name))
+            .and_then(|i| i.as_table_mut())
+        {
+            set_version(deps_table, key, version, registry)?;
         }
+        Ok(())
+    })
+}
 
-        if let Some(target_table) = manifest
+/// Set the version requirement of `key` in `[workspace.dependencies]` of `manifest_path`
+fn set_workspace_dependency_version(
+    manifest_path: &Path,
+    key: &str,
+    version: &str,
+    registry: bool,
+) -> Result<(), FatalError> {
+    let manifest = parse_cargo_config(manifest_path)?;
+    let dep = manifest
+        .get("workspace")
+        .and_then(|w| w.get("dependencies"))
+        .and_then(|deps| deps.get(key));
+    if dep.is_none() {
+        return Ok(());
+    }
+
+    edit_manifest(manifest_path, |manifest| {
+        if let Some(deps_table) = manifest
             .as_table_mut()
-            .get_mut("target")
+            .get_mut("workspace")
+            .and_then(|i| i.as_table_mut())
+            .and_then(|t| t.get_mut("dependencies"))
             .and_then(|i| i.as_table_mut())
         {
-            for (_target_name, target_specific_item) in target_table.iter_mut() {
-                for key in dep_table_names {
-                    if let Some(deps_table) = target_specific_item
-                        .as_table_mut()
-                        .and_then(|t| t.get_mut(key))
-                        .and_then(|i| i.as_table_mut())
-                    {
-                        set_version(deps_table, name, version)?;
+            set_version(deps_table, key, version, registry)?;
+        }
+        Ok(())
+    })
+}
+
+/// Remove the `version` of path dev-dependencies on `name`, `cargo publish` then leaves them out
+pub fn strip_dev_dependency_version(manifest_path: &Path, name: &str) -> Result<(), FatalError> {
+    let manifest = parse_cargo_config(manifest_path)?;
+    let mut dev_tables: Vec<&Value> = manifest.get("dev-dependencies").into_iter().collect();
+    if let Some(targets) = manifest.get("target").and_then(|t| t.as_table()) {
+        dev_tables.extend(targets.values().filter_map(|t| t.get("dev-dependencies")));
+    }
+    let needs_update = dev_tables
+        .iter()
+        .filter_map(|deps| deps.as_table())
+        .flat_map(|deps| deps.iter())
+        .filter(|(key, dep)| {
+            dependency_name(key, dep.get("package").and_then(|p| p.as_str())) == name
+        })
+        .any(|(_, dep)| dep.get("path").is_some() && dep.get("version").is_some());
+    if !needs_update {
+        return Ok(());
+    }
+
+    edit_manifest(manifest_path, |manifest| {
+        let mut dev_tables = Vec::new();
+        for (key, item) in manifest.as_table_mut().iter_mut() {
+            if key == "dev-dependencies" {
+                dev_tables.extend(item.as_table_mut());
+            } else if key == "target" {
+                if let Some(targets) = item.as_table_mut() {
+                    for (_, target) in targets.iter_mut() {
+                        dev_tables.extend(
+                            target
+                                .as_table_mut()
+                                .and_then(|t| t.get_mut("dev-dependencies"))
+                                .and_then(|i| i.as_table_mut()),
+                        );
+                    }
+                }
+            }
+        }
+
+        for deps_table in dev_tables {
+            for (key, dep_item) in deps_table.iter_mut() {
+                let package = dep_item
+                    .as_table_like()
+                    .and_then(|t| t.get("package"))
+                    .and_then(|p| p.as_str())
+                    .map(|p| p.to_owned());
+                if dependency_name(key, package.as_deref()) != name {
+                    continue;
+                }
+                if let Some(table) = dep_item.as_inline_table_mut() {
+                    if table.contains_key("path") {
+                        table.remove("version");
+                    }
+                } else if let Some(table) = dep_item.as_table_mut() {
+                    if table.contains_key("path") {
+                        table.remove("version");
                     }
                 }
             }
         }
+        Ok(())
+    })
+}
+
+/// Whether a manifest field is `{ workspace = true }`
+fn is_workspace_inherited(item: &Value) -> bool {
+    item.get("workspace").and_then(|w| w.as_bool()) == Some(true)
+}
+
+fn edit_manifest(
+    manifest_path: &Path,
+    edit: impl FnOnce(&mut toml_edit::Document) -> Result<(), FatalError>,
+) -> Result<(), FatalError> {
+    let temp_manifest_path = manifest_path
+        .parent()
+        .unwrap_or_else(|| Path::new("."))
+        .join("Cargo.toml.work");
+
+    {
+        let manifest = load_from_file(manifest_path)?;
+        let mut manifest: toml_edit::Document = manifest.parse().map_err(FatalError::from)?;
+        edit(&mut manifest)?;
 
         let mut file_out = File::create(&temp_manifest_path).map_err(FatalError::from)?;
         file_out
@@ -168,37 +421,295 @@ pub fn set_dependency_version(
     Ok(())
 }
 
+/// The package a dependency table entry refers to, taking renames into account
+fn dependency_name<'k>(key: &'k str, package: Option<&'k str>) -> &'k str {
+    package.unwrap_or(key)
+}
+
+/// Set the `version` of `key` in `deps_table`, replacing a bare requirement string of a `registry`
+/// dependency in place
 fn set_version(
     deps_table: &mut toml_edit::Table,
-    name: &str,
+    key: &str,
     version: &str,
+    registry: bool,
 ) -> Result<(), FatalError> {
-    let dep_item = match deps_table.get_mut(name) {
-        Some(item) => item,
-        None => {
-            return Ok(());
-        }
-    };
+    let dep_item = &mut deps_table[key];
     if dep_item.is_table_like() {
         dep_item["version"] = toml_edit::value(version);
+        Ok(())
+    } else if let Some(req) = dep_item.as_value().filter(|v| registry && v.is_str()) {
+        let req = toml_edit::decorated(version.into(), req.decor().prefix(), req.decor().suffix());
+        *dep_item = toml_edit::Item::Value(req);
+        Ok(())
     } else {
-        return Err(FatalError::InvalidCargoFileFormat(
+        Err(FatalError::InvalidCargoFileFormat(
             "Intra-workspace dependencies should use both version and path".into(),
-        ));
+        ))
     }
+}
 
-    Ok(())
+/// Where a [`Patch`] is declared
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PatchTable {
+    /// `[patch.<source>]`
+    Patch(String),
+    /// `[replace]`, keyed by a package ID spec like `foo:0.1.0`
+    Replace,
+}
+
+impl std::fmt::Display for PatchTable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PatchTable::Patch(source) => write!(f, "[patch.{}]", source),
+            PatchTable::Replace => write!(f, "[replace]"),
+        }
+    }
+}
+
+/// A `[patch]` or `[replace]` entry overriding a crate with its local path
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Patch {
+    pub table: PatchTable,
+    pub key: String,
+    /// The `version` requirement of a `[patch]`, the exact version of a `[replace]`
+    pub version: Option<String>,
+}
+
+/// `[patch]` and `[replace]` entries of `manifest_path` pointing at the crate `name` in `crate_root`
+pub fn find_patches(
+    manifest_path: &Path,
+    name: &str,
+    crate_root: &Path,
+) -> Result<Vec<Patch>, FatalError> {
+    let manifest = parse_cargo_config(manifest_path)?;
+    let manifest_root = manifest_path.parent().unwrap_or_else(|| Path::new("."));
+    let points_at_crate = |dep: &Value| {
+        dep.get("path")
+            .and_then(|p| p.as_str())
+            .map(|path| same_path(&manifest_root.join(path), crate_root))
+            .unwrap_or(false)
+    };
+
+    let mut patches = Vec::new();
+    if let Some(sources) = manifest.get("patch").and_then(|p| p.as_table()) {
+        for (source, deps) in sources {
+            for (key, dep) in deps.as_table().into_iter().flatten() {
+                let package = dep.get("package").and_then(|p| p.as_str());
+                if dependency_name(key, package) == name && points_at_crate(dep) {
+                    patches.push(Patch {
+                        table: PatchTable::Patch(source.clone()),
+                        key: key.clone(),
+                        version: dep
+                            .get("version")
+                            .and_then(|v| v.as_str())
+                            .map(|v| v.to_owned()),
+                    });
+                }
+            }
+        }
+    }
+    if let Some(replace) = manifest.get("replace").and_then(|r| r.as_table()) {
+        for (spec, dep) in replace {
+            let (spec_name, version) = parse_package_spec(spec);
+            if spec_name == name && points_at_crate(dep) {
+                patches.push(Patch {
+                    table: PatchTable::Replace,
+                    key: spec.clone(),
+                    version: version.map(|v| v.to_owned()),
+                });
+            }
+        }
+    }
+    Ok(patches)
+}
+
+/// Point `patch` at `version`, or remove it with `None`
+pub fn set_patch_version(
+    manifest_path: &Path,
+    patch: &Patch,
+    version: Option<&str>,
+) -> Result<(), FatalError> {
+    edit_manifest(manifest_path, |manifest| {
+        let table = match &patch.table {
+            PatchTable::Patch(source) => manifest["patch"][source.as_str()].as_table_mut(),
+            PatchTable::Replace => manifest["replace"].as_table_mut(),
+        };
+        let table = table.ok_or_else(|| {
+            FatalError::InvalidCargoFileFormat(format!("{} should be a table", patch.table))
+        })?;
+
+        match (version, &patch.table) {
+            (None, _) => {
+                table.remove(&patch.key);
+            }
+            (Some(version), PatchTable::Patch(_)) => {
+                table[&patch.key]["version"] = toml_edit::value(version);
+            }
+            (Some(version), PatchTable::Replace) => {
+                // The version is part of the key
+                let old_version = patch.version.as_deref().unwrap_or("");
+                let spec = &patch.key[..patch.key.len() - old_version.len()];
+                if let Some(item) = table.remove(&patch.key) {
+                    *table.entry(&format!("{}{}", spec, version)) = item;
+                }
+            }
+        }
+        Ok(())
+    })
 }
 
-pub fn update_lock(manifest_path: &Path) -> Result<(), FatalError> {
-    cargo_metadata::MetadataCommand::new()
-        .manifest_path(manifest_path)
-        .exec()
-        .map_err(FatalError::from)?;
+/// Split a package ID spec, e.g. `foo:0.1.0` or
+/// `https://github.com/rust-lang/crates.io-index#foo:0.1.0`, into the crate name and version
+fn parse_package_spec(spec: &str) -> (&str, Option<&str>) {
+    let (url, fragment) = match spec.find('#') {
+        Some(i) => (&spec[..i], &spec[i + 1..]),
+        None if spec.contains("://") => (spec, ""),
+        None => ("", spec),
+    };
+    let url_name = url.trim_end_matches('/').rsplit('/').next().unwrap_or("");
+    if fragment.is_empty() {
+        (url_name, None)
+    } else if semver::Version::parse(fragment).is_ok() {
+        (url_name, Some(fragment))
+    } else {
+        match fragment.rfind(&[':', '@'][..]) {
+            Some(i) => (&fragment[..i], Some(&fragment[i + 1..])),
+            None => (fragment, None),
+        }
+    }
+}
+
+fn same_path(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// Update the lock entry of crate `name` after its manifest changed from version `prev_version`.
+///
+/// The version makes the package spec unambiguous when the lock file holds other versions of
+/// `name`, e.g. from the registry.
+pub fn update_lock(
+    manifest_path: &Path,
+    lock_path: &Path,
+    name: &str,
+    prev_version: &str,
+) -> Result<(), FatalError> {
+    let before = match fs::read_to_string(lock_path) {
+        Ok(before) => before,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            log::debug!("No {}, nothing to update", lock_path.display());
+            return Ok(());
+        }
+        Err(err) => return Err(err.into()),
+    };
+
+    let cargo = cargo();
+    // `name:version` rather than `name@version`, which older cargo versions reject
+    let spec = format!("{}:{}", name, prev_version);
+    let updated = call(
+        vec![
+            &cargo,
+            "update",
+            "--manifest-path",
+            manifest_path.to_str().unwrap(),
+            "-p",
+            &spec,
+            "--offline",
+        ],
+        false,
+    )?;
+    if !updated {
+        fs::write(lock_path, &before)?;
+        return Err(FatalError::LockUpdateFailed(name.to_owned()));
+    }
+
+    let after = fs::read_to_string(lock_path)?;
+    let changed = changed_lock_entries(&before, &after, &|_| true)?;
+    if !changed.is_empty() {
+        fs::write(lock_path, &before)?;
+        return Err(FatalError::UnrelatedLockChanges(changed));
+    }
 
     Ok(())
 }
 
+/// Lock file packages removed (`-`) or added (`+`) between two versions of a lock file, ignoring
+/// version changes of the local crates for which `bumped` is true
+pub fn changed_lock_entries(
+    before: &str,
+    after: &str,
+    bumped: &dyn Fn(&str) -> bool,
+) -> Result<Vec<String>, FatalError> {
+    let before = lock_entries(before, bumped)?;
+    let after = lock_entries(after, bumped)?;
+    let removed = before.difference(&after).map(|e| format!("-{}", e));
+    let added = after.difference(&before).map(|e| format!("+{}", e));
+    Ok(removed.chain(added).collect())
+}
+
+/// Lock file packages as `name version (source)`, with `*` for the version of `bumped` local
+/// crates and the names of the dependencies
+fn lock_entries(lock: &str, bumped: &dyn Fn(&str) -> bool) -> Result<BTreeSet<String>, FatalError> {
+    let lock: Value = lock.parse()?;
+    let entries = lock
+        .get("package")
+        .and_then(|p| p.as_array())
+        .map(|packages| {
+            packages
+                .iter()
+                .filter_map(|p| {
+                    let name = p.get("name")?.as_str()?;
+                    let mut version = p.get("version")?.as_str()?;
+                    let mut entry = match p.get("source").and_then(|s| s.as_str()) {
+                        Some(source) => format!("{} {} ({})", name, version, source),
+                        None => {
+                            if bumped(name) {
+                                version = "*";
+                            }
+                            format!("{} {}", name, version)
+                        }
+                    };
+                    // Versions of dependencies are only written when ambiguous, depending on
+                    // the lock file format, so only compare names
+                    let mut deps: Vec<_> = p
+                        .get("dependencies")
+                        .and_then(|d| d.as_array())
+                        .into_iter()
+                        .flatten()
+                        .filter_map(|d| d.as_str())
+                        .filter_map(|d| d.split_whitespace().next())
+                        .collect();
+                    if !deps.is_empty() {
+                        deps.sort_unstable();
+                        entry.push_str(&format!(" [{}]", deps.join(", ")));
+                    }
+                    Some(entry)
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    Ok(entries)
+}
+
+/// `cargo update -p <name>`, moving the lock file to the latest compatible version of `name`
+pub fn update_package(manifest_path: &Path, name: &str, dry_run: bool) -> Result<bool, FatalError> {
+    let cargo = cargo();
+    call(
+        vec![
+            &cargo,
+            "update",
+            "--manifest-path",
+            manifest_path.to_str().unwrap(),
+            "-p",
+            name,
+        ],
+        dry_run,
+    )
+}
+
 pub fn parse_cargo_config(manifest_path: &Path) -> Result<Value, FatalError> {
     let cargo_file_content = load_from_file(manifest_path).map_err(FatalError::from)?;
     cargo_file_content.parse().map_err(FatalError::from)
@@ -228,6 +739,42 @@ mod test {
         }
     }
 
+    mod crate_checksum {
+        use super::*;
+
+        #[test]
+        fn packaged_crate() {
+            let temp = assert_fs::TempDir::new().unwrap();
+            temp.child("package/foo-0.1.0.crate")
+                .write_str("abc")
+                .unwrap();
+
+            assert_eq!(
+                crate_checksum(temp.path(), "foo", "0.1.0")
+                    .unwrap()
+                    .as_deref(),
+                Some("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad")
+            );
+            assert_eq!(crate_checksum(temp.path(), "foo", "0.2.0").unwrap(), None);
+
+            temp.close().unwrap();
+        }
+    }
+
+    mod searched_version {
+        use super::*;
+
+        #[test]
+        fn exact_name() {
+            let output = "\
+foo-derive = \"0.3.0\"    # Derive for foo
+foo = \"0.2.1\"           # The foo crate
+";
+            assert_eq!(searched_version(output, "foo").as_deref(), Some("0.2.1"));
+            assert_eq!(searched_version(output, "bar"), None);
+        }
+    }
+
     mod set_package_version {
         use super::*;
 
@@ -243,7 +790,7 @@ mod test {
                 .unwrap();
             assert_eq!(meta.packages[0].version.to_string(), "0.1.0");
 
-            set_package_version(manifest_path.path(), "2.0.0").unwrap();
+            set_package_version(manifest_path.path(), manifest_path.path(), "2.0.0").unwrap();
 
             let meta = cargo_metadata::MetadataCommand::new()
                 .manifest_path(manifest_path.path())
@@ -253,11 +800,39 @@ mod test {
 
             temp.close().unwrap();
         }
+
+        #[test]
+        fn workspace_inherited() {
+            let temp = assert_fs::TempDir::new().unwrap();
+            temp.copy_from("tests/fixtures/inherit_ws", &["**"])
+                .unwrap();
+            let manifest_path = temp.child("a/Cargo.toml");
+            let ws_manifest_path = temp.child("Cargo.toml");
+
+            set_package_version(manifest_path.path(), ws_manifest_path.path(), "0.1.1").unwrap();
+
+            manifest_path.assert(predicate::path::eq_file(Path::new(
+                "tests/fixtures/inherit_ws/a/Cargo.toml",
+            )));
+            let meta = cargo_metadata::MetadataCommand::new()
+                .manifest_path(ws_manifest_path.path())
+                .exec()
+                .unwrap();
+            for pkg in &meta.packages {
+                assert_eq!(pkg.version.to_string(), "0.1.1");
+            }
+
+            temp.close().unwrap();
+        }
     }
 
     mod set_dependency_version {
         use super::*;
 
+        pub(super) fn table(name: &'static str) -> DependencyTable {
+            DependencyTable { name, target: None }
+        }
+
         #[test]
         fn preserve_table_order() {
             let temp = assert_fs::TempDir::new().unwrap();
@@ -280,7 +855,14 @@ mod test {
                 )
                 .unwrap();
 
-            set_dependency_version(manifest_path.path(), "foo", "2.0").unwrap();
+            set_dependency_version(
+                manifest_path.path(),
+                manifest_path.path(),
+                &table("dependencies"),
+                "foo",
+                "2.0",
+            )
+            .unwrap();
 
             manifest_path.assert(
                 predicate::str::similar(
@@ -326,7 +908,14 @@ mod test {
                 )
                 .unwrap();
 
-            set_dependency_version(manifest_path.path(), "foo", "2.0").unwrap();
+            set_dependency_version(
+                manifest_path.path(),
+                manifest_path.path(),
+                &table("dependencies"),
+                "foo",
+                "2.0",
+            )
+            .unwrap();
 
             manifest_path.assert(
                 predicate::str::similar(
@@ -372,7 +961,14 @@ mod test {
                 )
                 .unwrap();
 
-            set_dependency_version(manifest_path.path(), "foo", "2.0").unwrap();
+            set_dependency_version(
+                manifest_path.path(),
+                manifest_path.path(),
+                &table("dev-dependencies"),
+                "foo",
+                "2.0",
+            )
+            .unwrap();
 
             manifest_path.assert(
                 predicate::str::similar(
@@ -418,7 +1014,14 @@ mod test {
                 )
                 .unwrap();
 
-            set_dependency_version(manifest_path.path(), "foo", "2.0").unwrap();
+            set_dependency_version(
+                manifest_path.path(),
+                manifest_path.path(),
+                &table("build-dependencies"),
+                "foo",
+                "2.0",
+            )
+            .unwrap();
 
             manifest_path.assert(
                 predicate::str::similar(
@@ -443,7 +1046,7 @@ mod test {
         }
 
         #[test]
-        fn all_dependencies() {
+        fn only_given_table() {
             let temp = assert_fs::TempDir::new().unwrap();
             temp.copy_from("tests/fixtures/simple", &["**"]).unwrap();
             let manifest_path = temp.child("Cargo.toml");
@@ -468,7 +1071,14 @@ mod test {
                 )
                 .unwrap();
 
-            set_dependency_version(manifest_path.path(), "foo", "2.0").unwrap();
+            set_dependency_version(
+                manifest_path.path(),
+                manifest_path.path(),
+                &table("dev-dependencies"),
+                "foo",
+                "2.0",
+            )
+            .unwrap();
 
             manifest_path.assert(
                 predicate::str::similar(
@@ -480,10 +1090,10 @@ mod test {
     edition = "2018"
 
     [dependencies]
-    foo = { version = "2.0", path = "../" }
+    foo = { version = "1.0", path = "../" }
 
     [build-dependencies]
-    foo = { version = "2.0", path = "../" }
+    foo = { version = "1.0", path = "../" }
 
     [dev-dependencies]
     foo = { version = "2.0", path = "../" }
@@ -518,7 +1128,13 @@ mod test {
                 )
                 .unwrap();
 
-            let err = set_dependency_version(manifest_path.path(), "foo", "2.0");
+            let err = set_dependency_version(
+                manifest_path.path(),
+                manifest_path.path(),
+                &table("dependencies"),
+                "foo",
+                "2.0",
+            );
             assert!(err.is_err());
 
             temp.close().unwrap();
@@ -547,7 +1163,14 @@ mod test {
                 )
                 .unwrap();
 
-            set_dependency_version(manifest_path.path(), "foo", "2.0").unwrap();
+            set_dependency_version(
+                manifest_path.path(),
+                manifest_path.path(),
+                &table("dependencies"),
+                "foo",
+                "2.0",
+            )
+            .unwrap();
 
             manifest_path.assert(
                 predicate::str::similar(
@@ -573,9 +1196,375 @@ mod test {
         }
     }
 
+    mod set_dependency_version_renamed {
+        use super::*;
+
+        #[test]
+        fn target_specific() {
+            let temp = assert_fs::TempDir::new().unwrap();
+            temp.copy_from("tests/fixtures/simple", &["**"]).unwrap();
+            let manifest_path = temp.child("Cargo.toml");
+            manifest_path
+                .write_str(
+                    r#"
+    [package]
+    name = "t"
+    version = "0.1.0"
+
+    [dependencies]
+    foo-core = { version = "1.0", path = "../" }
+
+    [target.'cfg(unix)'.dev-dependencies]
+    foo_core = { version = "1.0", path = "../", package = "foo-core" }
+    core = { version = "1.0", path = "../core" }
+    "#,
+                )
+                .unwrap();
+
+            let table = DependencyTable {
+                name: "dev-dependencies",
+                target: Some("cfg(unix)".to_owned()),
+            };
+            set_dependency_version(
+                manifest_path.path(),
+                manifest_path.path(),
+                &table,
+                "foo_core",
+                "2.0",
+            )
+            .unwrap();
+
+            manifest_path.assert(
+                predicate::str::similar(
+                    r#"
+    [package]
+    name = "t"
+    version = "0.1.0"
+
+    [dependencies]
+    foo-core = { version = "1.0", path = "../" }
+
+    [target.'cfg(unix)'.dev-dependencies]
+    foo_core = { version = "2.0", path = "../", package = "foo-core" }
+    core = { version = "1.0", path = "../core" }
+    "#,
+                )
+                .from_utf8()
+                .from_file_path(),
+            );
+
+            temp.close().unwrap();
+        }
+    }
+
+    mod set_dependency_version_inherited {
+        use super::set_dependency_version::table;
+        use super::*;
+
+        #[test]
+        fn member_left_alone() {
+            let temp = assert_fs::TempDir::new().unwrap();
+            temp.copy_from("tests/fixtures/inherit_ws", &["**"])
+                .unwrap();
+            let manifest_path = temp.child("b/Cargo.toml");
+            let ws_manifest_path = temp.child("Cargo.toml");
+
+            set_dependency_version(
+                manifest_path.path(),
+                ws_manifest_path.path(),
+                &table("dependencies"),
+                "a",
+                "2.0",
+            )
+            .unwrap();
+
+            manifest_path.assert(predicate::path::eq_file(Path::new(
+                "tests/fixtures/inherit_ws/b/Cargo.toml",
+            )));
+
+            temp.close().unwrap();
+        }
+
+        #[test]
+        fn workspace_dependencies() {
+            let temp = assert_fs::TempDir::new().unwrap();
+            temp.copy_from("tests/fixtures/inherit_ws", &["**"])
+                .unwrap();
+            let manifest_path = temp.child("Cargo.toml");
+
+            set_dependency_version(
+                temp.child("b/Cargo.toml").path(),
+                manifest_path.path(),
+                &table("dependencies"),
+                "a",
+                "2.0",
+            )
+            .unwrap();
+
+            manifest_path.assert(
+                predicate::str::similar(
+                    r#"[workspace]
+members = ["a", "b"]
+
+[workspace.package]
+version = "0.1.0"
+
+[workspace.dependencies]
+a = { version = "2.0", path = "a" }
+"#,
+                )
+                .from_utf8()
+                .from_file_path(),
+            );
+
+            temp.close().unwrap();
+        }
+
+        #[test]
+        fn inline_workspace_table() {
+            let temp = assert_fs::TempDir::new().unwrap();
+            temp.copy_from("tests/fixtures/simple", &["**"]).unwrap();
+            let manifest_path = temp.child("Cargo.toml");
+            manifest_path
+                .write_str(
+                    r#"
+    [package]
+    name = "t"
+    version = "0.1.0"
+
+    [dependencies]
+    foo = { workspace = true }
+
+    [dev-dependencies]
+    foo = { version = "1.0", path = "../" }
+    "#,
+                )
+                .unwrap();
+
+            set_dependency_version(
+                manifest_path.path(),
+                manifest_path.path(),
+                &table("dev-dependencies"),
+                "foo",
+                "2.0",
+            )
+            .unwrap();
+
+            manifest_path.assert(
+                predicate::str::similar(
+                    r#"
+    [package]
+    name = "t"
+    version = "0.1.0"
+
+    [dependencies]
+    foo = { workspace = true }
+
+    [dev-dependencies]
+    foo = { version = "2.0", path = "../" }
+    "#,
+                )
+                .from_utf8()
+                .from_file_path(),
+            );
+
+            temp.close().unwrap();
+        }
+    }
+
+    mod strip_dev_dependency_version {
+        use super::*;
+
+        #[test]
+        fn path_only() {
+            let temp = assert_fs::TempDir::new().unwrap();
+            temp.copy_from("tests/fixtures/simple", &["**"]).unwrap();
+            let manifest_path = temp.child("Cargo.toml");
+            manifest_path
+                .write_str(
+                    r#"
+    [package]
+    name = "t"
+    version = "0.1.0"
+
+    [dependencies]
+    foo = { version = "1.0", path = "../" }
+
+    [dev-dependencies]
+    foo = { version = "1.0", path = "../" }
+
+    [target.'cfg(unix)'.dev-dependencies.foo]
+    version = "1.0"
+    path = "../"
+    "#,
+                )
+                .unwrap();
+
+            strip_dev_dependency_version(manifest_path.path(), "foo").unwrap();
+
+            manifest_path.assert(
+                predicate::str::similar(
+                    r#"
+    [package]
+    name = "t"
+    version = "0.1.0"
+
+    [dependencies]
+    foo = { version = "1.0", path = "../" }
+
+    [dev-dependencies]
+    foo = { path = "../" }
+
+    [target.'cfg(unix)'.dev-dependencies.foo]
+    path = "../"
+    "#,
+                )
+                .from_utf8()
+                .from_file_path(),
+            );
+
+            temp.close().unwrap();
+        }
+    }
+
+    mod patches {
+        use super::*;
+
+        const MANIFEST: &str = r#"[workspace]
+members = ["a", "b"]
+
+[patch.crates-io]
+a = { version = "0.1.0", path = "a" }
+other = { version = "0.1.0", path = "b" }
+
+[replace]
+"a:0.1.0" = { path = "a" }
+"#;
+
+        #[test]
+        fn find() {
+            let temp = assert_fs::TempDir::new().unwrap();
+            temp.copy_from("tests/fixtures/pure_ws", &["**"]).unwrap();
+            let manifest_path = temp.child("Cargo.toml");
+            manifest_path.write_str(MANIFEST).unwrap();
+
+            let patches = find_patches(manifest_path.path(), "a", temp.child("a").path()).unwrap();
+            assert_eq!(
+                patches,
+                [
+                    Patch {
+                        table: PatchTable::Patch("crates-io".to_owned()),
+                        key: "a".to_owned(),
+                        version: Some("0.1.0".to_owned()),
+                    },
+                    Patch {
+                        table: PatchTable::Replace,
+                        key: "a:0.1.0".to_owned(),
+                        version: Some("0.1.0".to_owned()),
+                    },
+                ]
+            );
+            let patches = find_patches(manifest_path.path(), "b", temp.child("b").path()).unwrap();
+            assert_eq!(patches, []);
+
+            temp.close().unwrap();
+        }
+
+        #[test]
+        fn update_and_remove() {
+            let temp = assert_fs::TempDir::new().unwrap();
+            temp.copy_from("tests/fixtures/pure_ws", &["**"]).unwrap();
+            let manifest_path = temp.child("Cargo.toml");
+            manifest_path.write_str(MANIFEST).unwrap();
+
+            let patches = find_patches(manifest_path.path(), "a", temp.child("a").path()).unwrap();
+            set_patch_version(manifest_path.path(), &patches[0], None).unwrap();
+            set_patch_version(manifest_path.path(), &patches[1], Some("0.2.0")).unwrap();
+
+            manifest_path.assert(
+                predicate::str::similar(
+                    r#"[workspace]
+members = ["a", "b"]
+
+[patch.crates-io]
+other = { version = "0.1.0", path = "b" }
+
+[replace]
+"a:0.2.0" = { path = "a" }
+"#,
+                )
+                .from_utf8()
+                .from_file_path(),
+            );
+
+            temp.close().unwrap();
+        }
+
+        #[test]
+        fn package_spec() {
+            assert_eq!(parse_package_spec("foo"), ("foo", None));
+            assert_eq!(parse_package_spec("foo:0.1.0"), ("foo", Some("0.1.0")));
+            assert_eq!(parse_package_spec("foo@0.1.0"), ("foo", Some("0.1.0")));
+            assert_eq!(
+                parse_package_spec("https://github.com/rust-lang/crates.io-index#foo:0.1.0"),
+                ("foo", Some("0.1.0"))
+            );
+            assert_eq!(
+                parse_package_spec("https://github.com/rust-lang/foo#0.1.0"),
+                ("foo", Some("0.1.0"))
+            );
+        }
+    }
+
     mod update_lock {
         use super::*;
 
+        #[test]
+        fn changed_entries() {
+            let before = r#"
+[[package]]
+name = "a"
+version = "0.1.0"
+dependencies = ["serde"]
+
+[[package]]
+name = "serde"
+version = "1.0.100"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+"#;
+            let after = r#"
+[[package]]
+name = "a"
+version = "0.2.0"
+dependencies = ["serde"]
+
+[[package]]
+name = "serde"
+version = "1.0.101"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+"#;
+            assert_eq!(
+                changed_lock_entries(before, before, &|_| false).unwrap(),
+                Vec::<String>::new()
+            );
+            assert_eq!(
+                changed_lock_entries(before, after, &|_| false).unwrap(),
+                [
+                    "-a 0.1.0 [serde]",
+                    "-serde 1.0.100 (registry+https://github.com/rust-lang/crates.io-index)",
+                    "+a 0.2.0 [serde]",
+                    "+serde 1.0.101 (registry+https://github.com/rust-lang/crates.io-index)",
+                ]
+            );
+            assert_eq!(
+                changed_lock_entries(before, after, &|name| name == "a").unwrap(),
+                [
+                    "-serde 1.0.100 (registry+https://github.com/rust-lang/crates.io-index)",
+                    "+serde 1.0.101 (registry+https://github.com/rust-lang/crates.io-index)",
+                ]
+            );
+        }
+
         #[test]
         fn in_pkg() {
             let temp = assert_fs::TempDir::new().unwrap();
@@ -583,12 +1572,12 @@ mod test {
             let manifest_path = temp.child("Cargo.toml");
             let lock_path = temp.child("Cargo.lock");
 
-            set_package_version(manifest_path.path(), "2.0.0").unwrap();
+            set_package_version(manifest_path.path(), manifest_path.path(), "2.0.0").unwrap();
             lock_path.assert(predicate::path::eq_file(Path::new(
                 "tests/fixtures/simple/Cargo.lock",
             )));
 
-            update_lock(manifest_path.path()).unwrap();
+            update_lock(manifest_path.path(), lock_path.path(), "simple", "0.1.0").unwrap();
             lock_path.assert(
                 predicate::path::eq_file(Path::new("tests/fixtures/simple/Cargo.lock")).not(),
             );
@@ -603,12 +1592,17 @@ mod test {
             let manifest_path = temp.child("b/Cargo.toml");
             let lock_path = temp.child("Cargo.lock");
 
-            set_package_version(manifest_path.path(), "2.0.0").unwrap();
+            set_package_version(
+                manifest_path.path(),
+                temp.child("Cargo.toml").path(),
+                "2.0.0",
+            )
+            .unwrap();
             lock_path.assert(predicate::path::eq_file(Path::new(
                 "tests/fixtures/pure_ws/Cargo.lock",
             )));
 
-            update_lock(manifest_path.path()).unwrap();
+            update_lock(manifest_path.path(), lock_path.path(), "b", "0.1.0").unwrap();
             lock_path.assert(
                 predicate::path::eq_file(Path::new("tests/fixtures/pure_ws/Cargo.lock")).not(),
             );
@@ -623,12 +1617,12 @@ mod test {
             let manifest_path = temp.child("Cargo.toml");
             let lock_path = temp.child("Cargo.lock");
 
-            set_package_version(manifest_path.path(), "2.0.0").unwrap();
+            set_package_version(manifest_path.path(), manifest_path.path(), "2.0.0").unwrap();
             lock_path.assert(predicate::path::eq_file(Path::new(
                 "tests/fixtures/mixed_ws/Cargo.lock",
             )));
 
-            update_lock(manifest_path.path()).unwrap();
+            update_lock(manifest_path.path(), lock_path.path(), "b", "0.1.0").unwrap();
             lock_path.assert(
                 predicate::path::eq_file(Path::new("tests/fixtures/mixed_ws/Cargo.lock")).not(),
             );
@@ -1,9 +1,11 @@
+use std::collections::BTreeMap;
 use std::fs::File;
 use std::io;
 use std::io::prelude::*;
 use std::path::{Path, PathBuf};
 
 use clap::arg_enum;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use crate::error::FatalError;
@@ -53,6 +55,10 @@ pub trait ConfigSource {
         None
     }
 
+    fn dev_version_level(&self) -> Option<DevVersionLevel> {
+        None
+    }
+
     fn consolidate_commits(&self) -> Option<bool> {
         None
     }
@@ -90,6 +96,10 @@ pub trait ConfigSource {
         None
     }
 
+    fn tag_kind(&self) -> Option<TagKind> {
+        None
+    }
+
     fn tag_prefix(&self) -> Option<&str> {
         None
     }
@@ -113,39 +123,127 @@ pub trait ConfigSource {
     fn dependent_version(&self) -> Option<DependentVersion> {
         None
     }
+
+    fn remove_patches(&self) -> Option<bool> {
+        None
+    }
+
+    fn dev_dependents(&self) -> Option<DevDependents> {
+        None
+    }
+
+    fn metadata(&self) -> Option<&str> {
+        None
+    }
+
+    fn check_api(&self) -> Option<bool> {
+        None
+    }
+
+    fn check_api_toolchain(&self) -> Option<&str> {
+        None
+    }
+
+    fn verify_commands(&self) -> Option<&[Command]> {
+        None
+    }
+
+    fn disable_verify(&self) -> Option<bool> {
+        None
+    }
+
+    fn downstream(&self) -> Option<&[Downstream]> {
+        None
+    }
+
+    fn push_targets(&self) -> Option<&[PushTarget]> {
+        None
+    }
 }
 
-#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+/// cargo-release configuration, as read from `release.toml` or `[package.metadata.release]`
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
 #[serde(deny_unknown_fields, default)]
 #[serde(rename_all = "kebab-case")]
 pub struct Config {
+    /// Path globs (`gitignore` style) ignored when looking for changes since the last release
     pub exclude_paths: Option<Vec<String>>,
+    /// Sign the git commits with GPG
     pub sign_commit: Option<bool>,
+    /// Sign the git tags with GPG
     pub sign_tag: Option<bool>,
+    /// Git remote to push to (default: `origin`)
     pub push_remote: Option<String>,
+    /// Cargo registry to publish to (default: crates.io)
     pub registry: Option<String>,
+    /// Skip the entire release process
     pub disable_release: Option<bool>,
+    /// Don't run `cargo publish`
     pub disable_publish: Option<bool>,
+    /// Don't run `git push`
     pub disable_push: Option<bool>,
+    /// Flags sent to the server when running `git push`
     pub push_options: Option<Vec<String>>,
+    /// Remotes to push to, instead of `push-remote`
+    pub push_targets: Option<Vec<PushTarget>>,
+    /// Pre-release extension of the next development version (default: `alpha.0`)
     pub dev_version_ext: Option<String>,
+    /// Don't bump to a development version after the release
     pub no_dev_version: Option<bool>,
+    /// Version field to bump for the next development version (default: `patch`)
+    pub dev_version_level: Option<DevVersionLevel>,
+    /// Use a single commit for each version bump of a workspace release
     pub consolidate_commits: Option<bool>,
+    /// Deprecated, a release is always pushed with a single `git push`
     pub consolidate_pushes: Option<bool>,
+    /// Commit message template for the release
     pub pre_release_commit_message: Option<String>,
-    // depreacted
+    /// Deprecated, use `post-release-commit-message`
     pub pro_release_commit_message: Option<String>,
+    /// Commit message template for the bump to the next development version
     pub post_release_commit_message: Option<String>,
+    /// Replacements applied to files for the release commit
     pub pre_release_replacements: Option<Vec<Replace>>,
+    /// Replacements applied to files for the post-release commit
     pub post_release_replacements: Option<Vec<Replace>>,
+    /// Command run before the release commit; a non-zero exit aborts the release
     pub pre_release_hook: Option<Command>,
+    /// Message template for the git tag
     pub tag_message: Option<String>,
+    /// Kind of git tag to create (default: `annotated`)
+    pub tag_kind: Option<TagKind>,
+    /// Prefix of the git tag (default based on the crate name in a workspace)
     pub tag_prefix: Option<String>,
+    /// Template for the git tag name (default: `{{prefix}}v{{version}}`)
     pub tag_name: Option<String>,
+    /// Don't create a git tag
     pub disable_tag: Option<bool>,
+    /// Features to enable for `cargo publish`
     pub enable_features: Option<Vec<String>>,
+    /// Enable all features for `cargo publish`
     pub enable_all_features: Option<bool>,
+    /// How to update workspace crates depending on the released crate
     pub dependent_version: Option<DependentVersion>,
+    /// Remove stale `[patch]` and `[replace]` entries for the released crate instead of updating them
+    pub remove_patches: Option<bool>,
+    /// How to handle workspace crates with a dev-dependency on the released crate
+    pub dev_dependents: Option<DevDependents>,
+    /// Build metadata template for the released version, e.g. `g{{git_sha_short}}`
+    pub metadata: Option<String>,
+    /// Refuse public API removals in semver compatible releases (requires nightly)
+    pub check_api: Option<bool>,
+    /// Nightly toolchain generating the rustdoc JSON for `check-api` (default: `nightly`)
+    pub check_api_toolchain: Option<String>,
+    /// Commands run from each crate's directory before the release commit
+    pub verify_commands: Option<Vec<Command>>,
+    /// Don't run `verify-commands`
+    pub disable_verify: Option<bool>,
+    /// Repositories to update to the released versions
+    pub downstream: Option<Vec<Downstream>>,
+    /// Named sets of fields, applied on top of the config files with `--profile`
+    pub profile: Option<BTreeMap<String, Config>>,
+    /// Config files to read before this one, relative to it
+    pub include: Option<Vec<PathBuf>>,
 }
 
 impl Config {
@@ -177,16 +275,26 @@ impl Config {
         if let Some(push_options) = source.push_options() {
             self.push_options = Some(push_options.to_owned());
         }
+        if let Some(push_targets) = source.push_targets() {
+            self.push_targets = Some(push_targets.to_owned());
+        }
         if let Some(dev_version_ext) = source.dev_version_ext() {
             self.dev_version_ext = Some(dev_version_ext.to_owned());
         }
         if let Some(no_dev_version) = source.no_dev_version() {
             self.no_dev_version = Some(no_dev_version);
         }
+        if let Some(dev_version_level) = source.dev_version_level() {
+            self.dev_version_level = Some(dev_version_level);
+        }
         if let Some(consolidate_commits) = source.consolidate_commits() {
             self.consolidate_commits = Some(consolidate_commits);
         }
+        // depreacted
         if let Some(consolidate_pushes) = source.consolidate_pushes() {
+            log::warn!(
+                "consolidate-pushes is deprecated, branch and tags are always pushed together"
+            );
             self.consolidate_pushes = Some(consolidate_pushes);
         }
         if let Some(pre_release_commit_message) = source.pre_release_commit_message() {
@@ -214,6 +322,9 @@ impl Config {
         if let Some(tag_message) = source.tag_message() {
             self.tag_message = Some(tag_message.to_owned());
         }
+        if let Some(tag_kind) = source.tag_kind() {
+            self.tag_kind = Some(tag_kind);
+        }
         if let Some(tag_prefix) = source.tag_prefix() {
             self.tag_prefix = Some(tag_prefix.to_owned());
         }
@@ -232,6 +343,30 @@ impl Config {
         if let Some(dependent_version) = source.dependent_version() {
             self.dependent_version = Some(dependent_version);
         }
+        if let Some(remove_patches) = source.remove_patches() {
+            self.remove_patches = Some(remove_patches);
+        }
+        if let Some(dev_dependents) = source.dev_dependents() {
+            self.dev_dependents = Some(dev_dependents);
+        }
+        if let Some(metadata) = source.metadata() {
+            self.metadata = Some(metadata.to_owned());
+        }
+        if let Some(check_api) = source.check_api() {
+            self.check_api = Some(check_api);
+        }
+        if let Some(check_api_toolchain) = source.check_api_toolchain() {
+            self.check_api_toolchain = Some(check_api_toolchain.to_owned());
+        }
+        if let Some(verify_commands) = source.verify_commands() {
+            self.verify_commands = Some(verify_commands.to_owned());
+        }
+        if let Some(disable_verify) = source.disable_verify() {
+            self.disable_verify = Some(disable_verify);
+        }
+        if let Some(downstream) = source.downstream() {
+            self.downstream = Some(downstream.to_owned());
+        }
     }
 
     pub fn exclude_paths(&self) -> Option<&[String]> {
@@ -273,6 +408,19 @@ impl Config {
             .unwrap_or(&[])
     }
 
+    /// `push-targets`, or a single target for `push-remote`
+    pub fn push_targets(&self) -> Vec<PushTarget> {
+        match self.push_targets.as_ref() {
+            Some(push_targets) => push_targets.clone(),
+            None => vec![PushTarget {
+                remote: self.push_remote().to_owned(),
+                refspec: None,
+                push_options: None,
+                disable_tags: None,
+            }],
+        }
+    }
+
     pub fn dev_version_ext(&self) -> &str {
         self.dev_version_ext.as_deref().unwrap_or("alpha.0")
     }
@@ -281,12 +429,12 @@ impl Config {
         self.no_dev_version.unwrap_or(false)
     }
 
-    pub fn consolidate_commits(&self) -> bool {
-        self.consolidate_commits.unwrap_or(false)
+    pub fn dev_version_level(&self) -> DevVersionLevel {
+        self.dev_version_level.unwrap_or_default()
     }
 
-    pub fn consolidate_pushes(&self) -> bool {
-        self.consolidate_pushes.unwrap_or(false)
+    pub fn consolidate_commits(&self) -> bool {
+        self.consolidate_commits.unwrap_or(false)
     }
 
     pub fn pre_release_commit_message(&self) -> &str {
@@ -325,6 +473,10 @@ impl Config {
             .unwrap_or("(cargo-release) {{crate_name}} version {{version}}")
     }
 
+    pub fn tag_kind(&self) -> TagKind {
+        self.tag_kind.unwrap_or_default()
+    }
+
     pub fn tag_prefix(&self, is_root: bool) -> &str {
         // crate_name as default tag prefix for multi-crate project
         self.tag_prefix
@@ -354,6 +506,41 @@ impl Config {
     pub fn dependent_version(&self) -> DependentVersion {
         self.dependent_version.unwrap_or_default()
     }
+
+    pub fn remove_patches(&self) -> bool {
+        self.remove_patches.unwrap_or(false)
+    }
+
+    pub fn dev_dependents(&self) -> DevDependents {
+        self.dev_dependents.unwrap_or_default()
+    }
+
+    pub fn metadata(&self) -> Option<&str> {
+        self.metadata.as_deref()
+    }
+
+    pub fn check_api(&self) -> bool {
+        self.check_api.unwrap_or(false)
+    }
+
+    pub fn check_api_toolchain(&self) -> &str {
+        self.check_api_toolchain.as_deref().unwrap_or("nightly")
+    }
+
+    pub fn verify_commands(&self) -> &[Command] {
+        self.verify_commands
+            .as_ref()
+            .map(|v| v.as_ref())
+            .unwrap_or(&[])
+    }
+
+    pub fn disable_verify(&self) -> bool {
+        self.disable_verify.unwrap_or(false)
+    }
+
+    pub fn downstream(&self) -> &[Downstream] {
+        self.downstream.as_ref().map(|v| v.as_ref()).unwrap_or(&[])
+    }
 }
 
 impl ConfigSource for Config {
@@ -393,6 +580,10 @@ impl ConfigSource for Config {
         self.push_options.as_ref().map(|v| v.as_ref())
     }
 
+    fn push_targets(&self) -> Option<&[PushTarget]> {
+        self.push_targets.as_ref().map(|v| v.as_ref())
+    }
+
     fn dev_version_ext(&self) -> Option<&str> {
         self.dev_version_ext.as_deref()
     }
@@ -401,6 +592,10 @@ impl ConfigSource for Config {
         self.no_dev_version
     }
 
+    fn dev_version_level(&self) -> Option<DevVersionLevel> {
+        self.dev_version_level
+    }
+
     fn consolidate_commits(&self) -> Option<bool> {
         self.consolidate_commits
     }
@@ -438,6 +633,10 @@ impl ConfigSource for Config {
         self.tag_message.as_deref()
     }
 
+    fn tag_kind(&self) -> Option<TagKind> {
+        self.tag_kind
+    }
+
     fn tag_prefix(&self) -> Option<&str> {
         self.tag_prefix.as_deref()
     }
@@ -461,22 +660,112 @@ impl ConfigSource for Config {
     fn dependent_version(&self) -> Option<DependentVersion> {
         self.dependent_version
     }
+
+    fn remove_patches(&self) -> Option<bool> {
+        self.remove_patches
+    }
+
+    fn dev_dependents(&self) -> Option<DevDependents> {
+        self.dev_dependents
+    }
+
+    fn metadata(&self) -> Option<&str> {
+        self.metadata.as_deref()
+    }
+
+    fn check_api(&self) -> Option<bool> {
+        self.check_api
+    }
+
+    fn check_api_toolchain(&self) -> Option<&str> {
+        self.check_api_toolchain.as_deref()
+    }
+
+    fn verify_commands(&self) -> Option<&[Command]> {
+        self.verify_commands.as_ref().map(|v| v.as_ref())
+    }
+
+    fn disable_verify(&self) -> Option<bool> {
+        self.disable_verify
+    }
+
+    fn downstream(&self) -> Option<&[Downstream]> {
+        self.downstream.as_ref().map(|v| v.as_ref())
+    }
+}
+
+/// A checkout of a repository depending on crates of the workspace
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct Downstream {
+    /// Path to the checkout, relative to the workspace root
+    pub path: PathBuf,
+    /// Template for the branch to commit the update to (default: `release-{{date}}`)
+    pub branch: Option<String>,
+}
+
+impl Downstream {
+    pub fn branch(&self) -> &str {
+        self.branch.as_deref().unwrap_or("release-{{date}}")
+    }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// A remote to push the release commit and tags to
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+#[serde(rename_all = "kebab-case")]
+pub struct PushTarget {
+    /// Git remote
+    pub remote: String,
+    /// Refspec pushing the release commit (default: `HEAD`, the current branch)
+    pub refspec: Option<String>,
+    /// Flags sent to the server, instead of `push-options`
+    pub push_options: Option<Vec<String>>,
+    /// Only push the release commit, without the tags
+    pub disable_tags: Option<bool>,
+}
+
+impl PushTarget {
+    pub fn refspec(&self) -> &str {
+        self.refspec.as_deref().unwrap_or("HEAD")
+    }
+
+    pub fn disable_tags(&self) -> bool {
+        self.disable_tags.unwrap_or(false)
+    }
+
+    /// The branch `refspec` updates on the remote, when releasing from `branch`
+    pub fn remote_branch<'s>(&'s self, branch: &'s str) -> &'s str {
+        let refspec = self.refspec().trim_start_matches('+');
+        let dst = refspec.rsplit(':').next().unwrap_or(refspec);
+        let dst = if dst == "HEAD" { branch } else { dst };
+        dst.trim_start_matches("refs/heads/")
+    }
+}
+
+/// A search and replace in a file
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(deny_unknown_fields)]
 pub struct Replace {
+    /// File to search and replace in
     pub file: PathBuf,
+    /// Regex matching the text to replace
     pub search: String,
+    /// Replacement template
     pub replace: String,
+    /// Minimum occurrences of `search` (default: 1)
     pub min: Option<usize>,
+    /// Maximum occurrences of `search`
     pub max: Option<usize>,
+    /// Exact occurrences of `search`
     pub exactly: Option<usize>,
+    /// Also apply when bumping to a pre-release level
     #[serde(default)]
     pub prerelease: bool,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// A command to run, either a single program name or a list of the program and its arguments
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
 #[serde(untagged)]
 pub enum Command {
     Line(String),
@@ -493,7 +782,8 @@ impl Command {
 }
 
 arg_enum! {
-    #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+    /// How to update the requirements of workspace crates depending on a released crate
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
     #[serde(rename_all = "kebab-case")]
     pub enum DependentVersion {
         Upgrade,
@@ -510,16 +800,86 @@ impl Default for DependentVersion {
     }
 }
 
+arg_enum! {
+    /// How to handle workspace crates with a dev-dependency on a released crate: like other
+    /// dependents (`include`), leaving their requirement alone and not ordering the release on
+    /// them (`ignore`), or also removing the `version` of path dev-dependencies so
+    /// `cargo publish` drops them (`strip`)
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+    #[serde(rename_all = "kebab-case")]
+    pub enum DevDependents {
+        Include,
+        Ignore,
+        Strip,
+    }
+}
+
+#[allow(clippy::derivable_impls)] // `#[default]` is newer than the MSRV
+impl Default for DevDependents {
+    fn default() -> Self {
+        DevDependents::Include
+    }
+}
+
+arg_enum! {
+    /// Kind of git tag: `annotated`, with `tag-message`, or `lightweight`, a plain ref to the
+    /// release commit
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+    #[serde(rename_all = "kebab-case")]
+    pub enum TagKind {
+        Annotated,
+        Lightweight,
+    }
+}
+
+#[allow(clippy::derivable_impls)] // `#[default]` is newer than the MSRV
+impl Default for TagKind {
+    fn default() -> Self {
+        TagKind::Annotated
+    }
+}
+
+arg_enum! {
+    /// Version field to bump for the next development version
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+    #[serde(rename_all = "kebab-case")]
+    pub enum DevVersionLevel {
+        Patch,
+        Minor,
+        Major,
+    }
+}
+
+#[allow(clippy::derivable_impls)] // `#[default]` is newer than the MSRV
+impl Default for DevVersionLevel {
+    fn default() -> Self {
+        DevVersionLevel::Patch
+    }
+}
+
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 #[serde(default)]
 struct CargoManifest {
     package: Option<CargoPackage>,
+    workspace: Option<CargoWorkspace>,
 }
 
 impl CargoManifest {
     fn into_config(self) -> Option<Config> {
         self.package.and_then(|p| p.into_config())
     }
+
+    fn into_workspace_config(self) -> Option<Config> {
+        self.workspace
+            .and_then(|w| w.metadata)
+            .and_then(|m| m.release)
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct CargoWorkspace {
+    metadata: Option<CargoMetadata>,
 }
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
@@ -568,17 +928,30 @@ fn load_from_file(path: &Path) -> io::Result<String> {
 fn get_config_from_manifest(manifest_path: &Path) -> Result<Option<Config>, FatalError> {
     if manifest_path.exists() {
         let m = load_from_file(manifest_path).map_err(FatalError::from)?;
-        let c: CargoManifest = toml::from_str(&m).map_err(FatalError::from)?;
+        let c: CargoManifest = toml::from_str(&m)
+            .map_err(|e| FatalError::InvalidCargoConfigKeys(manifest_path.to_owned(), e))?;
         Ok(c.into_config())
     } else {
         Ok(None)
     }
 }
 
+fn get_workspace_config_from_manifest(manifest_path: &Path) -> Result<Option<Config>, FatalError> {
+    if manifest_path.exists() {
+        let m = load_from_file(manifest_path).map_err(FatalError::from)?;
+        let c: CargoManifest = toml::from_str(&m)
+            .map_err(|e| FatalError::InvalidCargoConfigKeys(manifest_path.to_owned(), e))?;
+        Ok(c.into_workspace_config())
+    } else {
+        Ok(None)
+    }
+}
+
 fn get_config_from_file(file_path: &Path) -> Result<Option<Config>, FatalError> {
     if file_path.exists() {
         let c = load_from_file(file_path).map_err(FatalError::from)?;
-        let config = toml::from_str(&c).map_err(FatalError::from)?;
+        let config = toml::from_str(&c)
+            .map_err(|e| FatalError::InvalidCargoConfigKeys(file_path.to_owned(), e))?;
         Ok(Some(config))
     } else {
         Ok(None)
@@ -589,84 +962,870 @@ pub fn resolve_custom_config(file_path: &Path) -> Result<Option<Config>, FatalEr
     get_config_from_file(file_path)
 }
 
-/// Try to resolve workspace configuration source.
-///
-/// This tries the following sources in order, merging the results:
-/// 1. $HOME/.release.toml
-/// 2. $(workspace)/release.toml
-pub fn resolve_workspace_config(workspace_root: &Path) -> Result<Config, FatalError> {
+/// Where a layer of configuration was read from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigOrigin {
+    /// A TOML file, with the table holding the configuration (`None` for the top-level table)
+    File {
+        path: PathBuf,
+        table: Option<String>,
+    },
+    /// `package.publish = false` in a crate manifest
+    Unpublished(PathBuf),
+    /// A `CARGO_RELEASE_*` environment variable
+    Env(String),
+    /// Command line arguments
+    Args,
+}
+
+impl ConfigOrigin {
+    fn file(path: PathBuf) -> Self {
+        ConfigOrigin::File { path, table: None }
+    }
+
+    fn manifest(path: PathBuf) -> Self {
+        ConfigOrigin::File {
+            path,
+            table: Some("package.metadata.release".to_owned()),
+        }
+    }
+
+    fn profile(&self, name: &str) -> Option<Self> {
+        match self {
+            ConfigOrigin::File { path, table } => {
+                let profile = format!("profile.{}", name);
+                let table = match table {
+                    Some(table) => format!("{}.{}", table, profile),
+                    None => profile,
+                };
+                Some(ConfigOrigin::File {
+                    path: path.clone(),
+                    table: Some(table),
+                })
+            }
+            ConfigOrigin::Unpublished(_) | ConfigOrigin::Env(_) | ConfigOrigin::Args => None,
+        }
+    }
+
+    fn workspace_manifest(path: PathBuf) -> Self {
+        ConfigOrigin::File {
+            path,
+            table: Some("workspace.metadata.release".to_owned()),
+        }
+    }
+
+    /// The line defining `key` in this origin, if it is a file
+    pub fn find_line(&self, key: &str) -> Option<usize> {
+        match self {
+            ConfigOrigin::File { path, table } => {
+                let content = load_from_file(path).ok()?;
+                find_key_line(&content, table.as_deref(), key)
+            }
+            ConfigOrigin::Unpublished(_) | ConfigOrigin::Env(_) | ConfigOrigin::Args => None,
+        }
+    }
+}
+
+impl std::fmt::Display for ConfigOrigin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigOrigin::File {
+                path,
+                table: Some(table),
+            } => write!(f, "{} `[{}]`", path.display(), table),
+            ConfigOrigin::File { path, table: None } => write!(f, "{}", path.display()),
+            ConfigOrigin::Unpublished(path) => {
+                write!(f, "{} `package.publish = false`", path.display())
+            }
+            ConfigOrigin::Env(var) => write!(f, "`{}` environment variable", var),
+            ConfigOrigin::Args => write!(f, "command line"),
+        }
+    }
+}
+
+/// Configuration read from one source
+#[derive(Debug, Clone)]
+pub struct ConfigLayer {
+    pub origin: ConfigOrigin,
+    pub config: Config,
+}
+
+/// Merge layers of configuration, later layers taking precedence.
+pub fn merge_layers(layers: &[ConfigLayer]) -> Config {
     let mut config = Config::default();
+    for layer in layers {
+        config.update(&layer.config);
+    }
+    config
+}
 
+fn home_layers() -> Result<Vec<ConfigLayer>, FatalError> {
     // User-local configuration from home directory.
     let home_dir = dirs_next::home_dir();
     if let Some(mut home) = home_dir {
         home.push(".release.toml");
-        if let Some(cfg) = get_config_from_file(&home)? {
-            config.update(&cfg);
-        }
+        return file_layers(home);
     };
+    Ok(Vec::new())
+}
 
-    let default_config = workspace_root.join("release.toml");
-    let current_dir_config = get_config_from_file(&default_config)?;
-    if let Some(cfg) = current_dir_config {
-        config.update(&cfg);
+fn file_layers(path: PathBuf) -> Result<Vec<ConfigLayer>, FatalError> {
+    match get_config_from_file(&path)? {
+        Some(config) => with_includes(ConfigLayer {
+            origin: ConfigOrigin::file(path),
+            config,
+        }),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// `layer`, preceded by the files it includes.
+fn with_includes(layer: ConfigLayer) -> Result<Vec<ConfigLayer>, FatalError> {
+    let mut layers = Vec::new();
+    expand_includes(layer, &mut Vec::new(), &mut layers)?;
+    Ok(layers)
+}
+
+fn expand_includes(
+    layer: ConfigLayer,
+    stack: &mut Vec<PathBuf>,
+    layers: &mut Vec<ConfigLayer>,
+) -> Result<(), FatalError> {
+    let path = match &layer.origin {
+        ConfigOrigin::File { path, .. } => path.clone(),
+        ConfigOrigin::Unpublished(_) | ConfigOrigin::Env(_) | ConfigOrigin::Args => {
+            layers.push(layer);
+            return Ok(());
+        }
     };
+    let base = path.parent().unwrap_or_else(|| Path::new("."));
+
+    stack.push(path.canonicalize().unwrap_or_else(|_| path.clone()));
+    for include in layer.config.include.iter().flatten() {
+        let include_path = base.join(include);
+        let canonical = include_path
+            .canonicalize()
+            .map_err(|_| FatalError::ConfigIncludeNotFound(include_path.clone(), path.clone()))?;
+        if stack.contains(&canonical) {
+            return Err(FatalError::ConfigIncludeCycle(include_path));
+        }
+        let config = get_config_from_file(&include_path)?.unwrap_or_default();
+        let include_layer = ConfigLayer {
+            origin: ConfigOrigin::file(include_path),
+            config,
+        };
+        expand_includes(include_layer, stack, layers)?;
+    }
+    stack.pop();
 
-    Ok(config)
+    layers.push(layer);
+    Ok(())
 }
 
-/// Try to resolve configuration source.
+/// Configuration layers for the workspace, lowest precedence first.
 ///
-/// This tries the following sources in order, merging the results:
+/// This tries the following sources in order:
 /// 1. $HOME/.release.toml
 /// 2. $(workspace)/release.toml
-/// 4. $(crate)/release.toml
+/// 3. $(workspace)/Cargo.toml `workspace.metadata.release`
+pub fn workspace_layers(workspace_root: &Path) -> Result<Vec<ConfigLayer>, FatalError> {
+    let mut layers = Vec::new();
+    layers.extend(home_layers()?);
+    layers.extend(file_layers(workspace_root.join("release.toml"))?);
+
+    let manifest_path = workspace_root.join("Cargo.toml");
+    if let Some(config) = get_workspace_config_from_manifest(&manifest_path)? {
+        layers.extend(with_includes(ConfigLayer {
+            origin: ConfigOrigin::workspace_manifest(manifest_path),
+            config,
+        })?);
+    }
+
+    Ok(layers)
+}
+
+/// Configuration layers for a crate, lowest precedence first.
+///
+/// This tries the following sources in order:
+/// 1. The [`workspace_layers`]
+/// 2. $(crate)/release.toml
 /// 3. $(crate)/Cargo.toml `package.metadata.release`
 ///
 /// `$(crate)/Cargo.toml` is a way to differentiate configuration for the root crate and the
 /// workspace.
+pub fn crate_layers(
+    workspace_root: &Path,
+    manifest_path: &Path,
+) -> Result<Vec<ConfigLayer>, FatalError> {
+    let mut layers = workspace_layers(workspace_root)?;
+
+    let crate_root = manifest_path.parent().unwrap_or_else(|| Path::new("."));
+
+    // Project release file, already read for the root crate.
+    if crate_root != workspace_root {
+        layers.extend(file_layers(crate_root.join("release.toml"))?);
+    }
+
+    // Crate manifest.
+    if let Some(config) = get_config_from_manifest(manifest_path)? {
+        layers.extend(with_includes(ConfigLayer {
+            origin: ConfigOrigin::manifest(manifest_path.to_owned()),
+            config,
+        })?);
+    };
+
+    Ok(layers)
+}
+
+/// Try to resolve workspace configuration source.
+///
+/// See [`workspace_layers`] for the sources.
+pub fn resolve_workspace_config(workspace_root: &Path) -> Result<Config, FatalError> {
+    Ok(merge_layers(&workspace_layers(workspace_root)?))
+}
+
+/// Try to resolve configuration source.
+///
+/// See [`crate_layers`] for the sources.
 pub fn resolve_config(workspace_root: &Path, manifest_path: &Path) -> Result<Config, FatalError> {
-    let mut config = Config::default();
+    Ok(merge_layers(&crate_layers(workspace_root, manifest_path)?))
+}
 
-    // User-local configuration from home directory.
-    let home_dir = dirs_next::home_dir();
-    if let Some(mut home) = home_dir {
-        home.push(".release.toml");
-        if let Some(cfg) = get_config_from_file(&home)? {
-            config.update(&cfg);
+/// Every configuration layer that applies to the workspace, or to a crate when `manifest_path`
+/// is given, lowest precedence first.
+///
+/// On top of the implicit files, this covers the `--config` file, the selected `profile` from
+/// each of those files, `CARGO_RELEASE_*` environment variables, the command line arguments and,
+/// for crates, `package.publish = false`.
+pub fn resolve_layers(
+    workspace_root: &Path,
+    manifest_path: Option<&Path>,
+    isolated: bool,
+    custom_config: Option<&Path>,
+    profile: Option<&str>,
+    args: &dyn ConfigSource,
+) -> Result<Vec<ConfigLayer>, FatalError> {
+    let mut layers = Vec::new();
+
+    if !isolated {
+        match manifest_path {
+            Some(manifest_path) => layers.extend(crate_layers(workspace_root, manifest_path)?),
+            None => layers.extend(workspace_layers(workspace_root)?),
+        }
+    }
+
+    if let Some(custom_config) = custom_config {
+        // when calling with -c option
+        layers.extend(file_layers(custom_config.to_owned())?);
+    }
+
+    if let Some(profile) = profile {
+        layers = with_profile(layers, profile);
+    }
+
+    layers.extend(env_layers(std::env::vars_os().filter_map(
+        |(key, value)| Some((key.into_string().ok()?, value.into_string().ok()?)),
+    ))?);
+
+    let mut args_config = Config::default();
+    args_config.update(args);
+    layers.push(ConfigLayer {
+        origin: ConfigOrigin::Args,
+        config: args_config,
+    });
+
+    if let Some(manifest_path) = manifest_path {
+        // the publish flag in cargo file
+        let cargo_file = crate::cargo::parse_cargo_config(manifest_path)?;
+        if !cargo_file
+            .get("package")
+            .and_then(|f| f.as_table())
+            .and_then(|f| f.get("publish"))
+            .and_then(|f| f.as_bool())
+            .unwrap_or(true)
+        {
+            layers.push(ConfigLayer {
+                origin: ConfigOrigin::Unpublished(manifest_path.to_owned()),
+                config: Config {
+                    disable_publish: Some(true),
+                    ..Default::default()
+                },
+            });
+        }
+    }
+
+    Ok(layers)
+}
+
+/// `layers` with their `profile` table named `name` applied right after each of them, so a
+/// profile only overrides the file defining it and those before it.
+fn with_profile(layers: Vec<ConfigLayer>, name: &str) -> Vec<ConfigLayer> {
+    let mut profiled = Vec::with_capacity(layers.len());
+    for layer in layers {
+        let profile_layer = layer
+            .config
+            .profile
+            .as_ref()
+            .and_then(|profiles| profiles.get(name))
+            .and_then(|config| {
+                Some(ConfigLayer {
+                    origin: layer.origin.profile(name)?,
+                    config: config.clone(),
+                })
+            });
+        profiled.push(layer);
+        profiled.extend(profile_layer);
+    }
+    profiled
+}
+
+/// Fail when profile `name` isn't defined by the workspace config files, those of the crates at
+/// `manifest_paths` or the `--config` file.
+///
+/// A profile may only be defined by some crates, so this is checked once for the whole workspace
+/// rather than in [`resolve_layers`].
+pub fn check_profile(
+    workspace_root: &Path,
+    manifest_paths: &[&Path],
+    isolated: bool,
+    custom_config: Option<&Path>,
+    name: &str,
+) -> Result<(), FatalError> {
+    let mut layers = Vec::new();
+    if !isolated {
+        layers.extend(workspace_layers(workspace_root)?);
+        for manifest_path in manifest_paths {
+            layers.extend(crate_layers(workspace_root, manifest_path)?);
+        }
+    }
+    if let Some(custom_config) = custom_config {
+        layers.extend(file_layers(custom_config.to_owned())?);
+    }
+
+    let defined = layers.iter().any(|layer| {
+        matches!(layer.config.profile.as_ref(), Some(profiles) if profiles.contains_key(name))
+    });
+    if defined {
+        Ok(())
+    } else {
+        Err(FatalError::UnknownProfile(name.to_owned()))
+    }
+}
+
+const ENV_PREFIX: &str = "CARGO_RELEASE_";
+/// Selects the profile rather than setting a field
+const ENV_PROFILE: &str = "CARGO_RELEASE_PROFILE";
+/// Fields that only make sense in a file: includes are relative to it and profiles are tables of it
+const FILE_ONLY_FIELDS: &[&str] = &["include", "profile"];
+
+/// Configuration from `CARGO_RELEASE_*` environment variables, one layer per variable.
+///
+/// The variable name maps to a field (`CARGO_RELEASE_PUSH_REMOTE` to `push-remote`) and its
+/// value is read as a TOML value, falling back to a plain string, so lists and tables can be
+/// passed as well (`CARGO_RELEASE_PUSH_OPTIONS='["ci.skip"]'`).
+pub fn env_layers(
+    vars: impl IntoIterator<Item = (String, String)>,
+) -> Result<Vec<ConfigLayer>, FatalError> {
+    let fields = config_fields();
+    let mut vars: Vec<_> = vars
+        .into_iter()
+        .filter(|(var, _)| var.starts_with(ENV_PREFIX) && var != ENV_PROFILE)
+        .collect();
+    vars.sort();
+
+    let mut layers = Vec::new();
+    for (var, value) in vars {
+        let key = var[ENV_PREFIX.len()..].to_lowercase().replace('_', "-");
+        if !fields.contains(&key.as_str()) {
+            log::warn!("Ignoring `{}`, `{}` is not a config field", var, key);
+            continue;
         }
+        if FILE_ONLY_FIELDS.contains(&key.as_str()) {
+            return Err(FatalError::UnsupportedEnvConfig(var, key));
+        }
+
+        let config = parse_env_value(&key, &value)
+            .map_err(|err| FatalError::InvalidEnvConfig(var.clone(), err))?;
+        layers.push(ConfigLayer {
+            origin: ConfigOrigin::Env(var),
+            config,
+        });
+    }
+    Ok(layers)
+}
+
+fn parse_env_value(key: &str, value: &str) -> Result<Config, toml::de::Error> {
+    let field = |value: toml::Value| -> Result<Config, toml::de::Error> {
+        let mut table = toml::value::Table::new();
+        table.insert(key.to_owned(), value);
+        toml::Value::Table(table).try_into()
     };
 
-    let crate_root = manifest_path.parent().unwrap_or_else(|| Path::new("."));
+    let typed = toml::from_str::<toml::value::Table>(&format!("value = {}", value))
+        .ok()
+        .and_then(|mut table| table.remove("value"));
+    match typed {
+        Some(typed) => field(typed).or_else(|_| field(toml::Value::String(value.to_owned()))),
+        None => field(toml::Value::String(value.to_owned())),
+    }
+}
 
-    if crate_root != workspace_root {
-        let default_config = workspace_root.join("release.toml");
-        let current_dir_config = get_config_from_file(&default_config)?;
-        if let Some(cfg) = current_dir_config {
-            config.update(&cfg);
-        };
+/// The keys accepted in a config file
+pub fn config_fields() -> &'static [&'static str] {
+    use serde::de::{self, Visitor};
+
+    /// Records the field names `Config` asks for when deserializing
+    struct FieldNames<'f>(&'f mut &'static [&'static str]);
+
+    impl<'de, 'f> de::Deserializer<'de> for FieldNames<'f> {
+        type Error = de::value::Error;
+
+        fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Self::Error> {
+            Err(de::Error::custom("only structs are supported"))
+        }
+
+        fn deserialize_struct<V: Visitor<'de>>(
+            self,
+            _name: &'static str,
+            fields: &'static [&'static str],
+            _visitor: V,
+        ) -> Result<V::Value, Self::Error> {
+            *self.0 = fields;
+            Err(de::Error::custom("only field names are recorded"))
+        }
+
+        serde::forward_to_deserialize_any! {
+            bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+            bytes byte_buf option unit unit_struct newtype_struct seq tuple
+            tuple_struct map enum identifier ignored_any
+        }
+    }
+
+    let mut fields: &'static [&'static str] = &[];
+    let _ = Config::deserialize(FieldNames(&mut fields));
+    fields
+}
+
+/// JSON Schema of config files
+pub fn schema() -> schemars::schema::RootSchema {
+    use schemars::schema::{Schema, SchemaObject};
+
+    // TOML has no null, unset fields are simply left out
+    fn remove_nulls(schema: &mut SchemaObject) {
+        if let Some(object) = schema.object.as_mut() {
+            for property in object.properties.values_mut() {
+                if let Schema::Object(property) = property {
+                    if let Some(metadata) = property.metadata.as_mut() {
+                        if metadata.default == Some(serde_json::Value::Null) {
+                            metadata.default = None;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    let settings = schemars::gen::SchemaSettings::draft07().with(|s| {
+        s.option_nullable = false;
+        s.option_add_null_type = false;
+    });
+    let mut root = settings.into_generator().into_root_schema_for::<Config>();
+    remove_nulls(&mut root.schema);
+    for definition in root.definitions.values_mut() {
+        if let Schema::Object(definition) = definition {
+            remove_nulls(definition);
+        }
     }
+    root
+}
+
+/// Fields superseded by another field
+const DEPRECATED_FIELDS: &[(&str, Option<&str>)] = &[
+    (
+        "pro-release-commit-message",
+        Some("post-release-commit-message"),
+    ),
+    ("consolidate-pushes", None),
+];
+
+/// Fields only read from the workspace configuration
+const WORKSPACE_FIELDS: &[&str] = &[
+    "consolidate-commits",
+    "push-remote",
+    "push-targets",
+    "downstream",
+];
+
+/// A problem found in a configuration source
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigIssue {
+    InvalidToml(String),
+    UnknownKey {
+        key: String,
+        suggestion: Option<&'static str>,
+    },
+    InvalidValue {
+        key: String,
+        error: String,
+    },
+    Deprecated {
+        key: String,
+        replacement: Option<&'static str>,
+    },
+    IgnoredInCrate {
+        key: String,
+    },
+    IncludeNotFound(String),
+    IncludeCycle(String),
+}
 
-    // Project release file.
-    let default_config = crate_root.join("release.toml");
-    let current_dir_config = get_config_from_file(&default_config)?;
-    if let Some(cfg) = current_dir_config {
-        config.update(&cfg);
+impl ConfigIssue {
+    /// Whether the source fails to load, rather than loading with surprising results
+    pub fn is_error(&self) -> bool {
+        match self {
+            ConfigIssue::InvalidToml(_)
+            | ConfigIssue::UnknownKey { .. }
+            | ConfigIssue::InvalidValue { .. }
+            | ConfigIssue::IncludeNotFound(_)
+            | ConfigIssue::IncludeCycle(_) => true,
+            ConfigIssue::Deprecated { .. } | ConfigIssue::IgnoredInCrate { .. } => false,
+        }
+    }
+
+    /// The key the issue is about, if any
+    pub fn key(&self) -> Option<&str> {
+        match self {
+            ConfigIssue::InvalidToml(_) => None,
+            ConfigIssue::UnknownKey { key, .. }
+            | ConfigIssue::InvalidValue { key, .. }
+            | ConfigIssue::Deprecated { key, .. }
+            | ConfigIssue::IgnoredInCrate { key } => Some(key),
+            ConfigIssue::IncludeNotFound(_) | ConfigIssue::IncludeCycle(_) => Some("include"),
+        }
+    }
+}
+
+impl std::fmt::Display for ConfigIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigIssue::InvalidToml(error) => write!(f, "invalid TOML: {}", error),
+            ConfigIssue::UnknownKey {
+                key,
+                suggestion: Some(suggestion),
+            } => write!(f, "unknown key `{}`, did you mean `{}`?", key, suggestion),
+            ConfigIssue::UnknownKey {
+                key,
+                suggestion: None,
+            } => write!(f, "unknown key `{}`", key),
+            ConfigIssue::InvalidValue { key, error } => {
+                write!(f, "invalid value for `{}`: {}", key, error)
+            }
+            ConfigIssue::Deprecated {
+                key,
+                replacement: Some(replacement),
+            } => write!(f, "`{}` is deprecated, use `{}` instead", key, replacement),
+            ConfigIssue::Deprecated {
+                key,
+                replacement: None,
+            } => write!(f, "`{}` is deprecated and has no effect", key),
+            ConfigIssue::IgnoredInCrate { key } => write!(
+                f,
+                "`{}` is ignored in crate config, set it in the workspace config instead",
+                key
+            ),
+            ConfigIssue::IncludeNotFound(path) => write!(f, "included file `{}` not found", path),
+            ConfigIssue::IncludeCycle(path) => {
+                write!(f, "including `{}` creates a cycle", path)
+            }
+        }
+    }
+}
+
+/// An issue along with the source it was found in
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigDiagnostic {
+    pub origin: ConfigOrigin,
+    pub issue: ConfigIssue,
+}
+
+/// Every config file of a workspace, along with whether it is crate-level config.
+///
+/// Unlike [`crate_layers`], this also lists sources that fail to load.
+pub fn workspace_sources(
+    workspace_root: &Path,
+    manifest_paths: &[&Path],
+) -> Vec<(ConfigOrigin, bool)> {
+    let mut sources = Vec::new();
+    if let Some(mut home) = dirs_next::home_dir() {
+        home.push(".release.toml");
+        sources.push((ConfigOrigin::file(home), false));
+    }
+    sources.push((
+        ConfigOrigin::file(workspace_root.join("release.toml")),
+        false,
+    ));
+    sources.push((
+        ConfigOrigin::workspace_manifest(workspace_root.join("Cargo.toml")),
+        false,
+    ));
+    for manifest_path in manifest_paths {
+        let crate_root = manifest_path.parent().unwrap_or_else(|| Path::new("."));
+        if crate_root != workspace_root {
+            sources.push((ConfigOrigin::file(crate_root.join("release.toml")), true));
+        }
+        sources.push((ConfigOrigin::manifest(manifest_path.to_path_buf()), true));
+    }
+    sources
+}
+
+/// Validate a config source, reporting every problem instead of stopping at the first.
+///
+/// Files included from the source are checked as well.
+pub fn check_source(origin: &ConfigOrigin, crate_level: bool) -> Vec<ConfigDiagnostic> {
+    check_source_with_includes(origin, crate_level, &mut Vec::new())
+}
+
+fn check_source_with_includes(
+    origin: &ConfigOrigin,
+    crate_level: bool,
+    stack: &mut Vec<PathBuf>,
+) -> Vec<ConfigDiagnostic> {
+    let (path, table) = match origin {
+        ConfigOrigin::File { path, table } => (path, table),
+        _ => return Vec::new(),
+    };
+    let content = match load_from_file(path) {
+        Ok(content) => content,
+        Err(_) => return Vec::new(),
+    };
+    let diagnostic = |origin: &ConfigOrigin, issue| ConfigDiagnostic {
+        origin: origin.clone(),
+        issue,
     };
 
-    // Crate manifest.
-    let current_dir_config = get_config_from_manifest(manifest_path)?;
-    if let Some(cfg) = current_dir_config {
-        config.update(&cfg);
+    let mut value = match toml::from_str::<toml::Value>(&content) {
+        Ok(value) => value,
+        Err(err) => {
+            return vec![diagnostic(
+                origin,
+                ConfigIssue::InvalidToml(err.to_string()),
+            )]
+        }
+    };
+    if let Some(table) = table {
+        for part in table.split('.') {
+            value = match value.as_table_mut().and_then(|t| t.remove(part)) {
+                Some(value) => value,
+                None => return Vec::new(),
+            };
+        }
+    }
+    let table = match value {
+        toml::Value::Table(table) => table,
+        _ => {
+            let issue = ConfigIssue::InvalidToml("expected a table".to_owned());
+            return vec![diagnostic(origin, issue)];
+        }
     };
 
-    Ok(config)
+    let mut diagnostics: Vec<_> = check_table(&table, crate_level)
+        .into_iter()
+        .map(|issue| diagnostic(origin, issue))
+        .collect();
+
+    if let Some(profiles) = table.get("profile").and_then(|p| p.as_table()) {
+        for (name, profile) in profiles {
+            let profile_origin = origin.profile(name).expect("files support profiles");
+            let issues = match profile {
+                toml::Value::Table(profile) => check_table(profile, crate_level),
+                _ => vec![ConfigIssue::InvalidValue {
+                    key: name.clone(),
+                    error: "expected a table".to_owned(),
+                }],
+            };
+            diagnostics.extend(issues.into_iter().map(|i| diagnostic(&profile_origin, i)));
+        }
+    }
+
+    if let Some(includes) = table.get("include").and_then(|i| i.as_array()) {
+        let base = path.parent().unwrap_or_else(|| Path::new("."));
+        stack.push(path.canonicalize().unwrap_or_else(|_| path.clone()));
+        for include in includes.iter().filter_map(|i| i.as_str()) {
+            let include_path = base.join(include);
+            match include_path.canonicalize() {
+                Ok(canonical) if stack.contains(&canonical) => {
+                    let issue = ConfigIssue::IncludeCycle(include.to_owned());
+                    diagnostics.push(diagnostic(origin, issue));
+                }
+                Ok(_) => diagnostics.extend(check_source_with_includes(
+                    &ConfigOrigin::file(include_path),
+                    crate_level,
+                    stack,
+                )),
+                Err(_) => {
+                    let issue = ConfigIssue::IncludeNotFound(include.to_owned());
+                    diagnostics.push(diagnostic(origin, issue));
+                }
+            }
+        }
+        stack.pop();
+    }
+
+    diagnostics
+}
+
+fn check_table(table: &toml::value::Table, crate_level: bool) -> Vec<ConfigIssue> {
+    let fields = config_fields();
+    let mut issues = Vec::new();
+    for (key, value) in table {
+        if key == "profile" {
+            // Profiles are checked by the caller with the origin of each profile
+            if !value.is_table() {
+                issues.push(ConfigIssue::InvalidValue {
+                    key: key.clone(),
+                    error: "expected a table of profiles".to_owned(),
+                });
+            }
+            continue;
+        }
+        if !fields.contains(&key.as_str()) {
+            issues.push(ConfigIssue::UnknownKey {
+                key: key.clone(),
+                suggestion: suggest_field(key, fields),
+            });
+            continue;
+        }
+
+        let mut single = toml::value::Table::new();
+        single.insert(key.clone(), value.clone());
+        if let Err(err) = toml::Value::Table(single).try_into::<Config>() {
+            issues.push(ConfigIssue::InvalidValue {
+                key: key.clone(),
+                error: err.to_string(),
+            });
+        }
+
+        if let Some((_, replacement)) = DEPRECATED_FIELDS.iter().find(|(k, _)| k == key) {
+            issues.push(ConfigIssue::Deprecated {
+                key: key.clone(),
+                replacement: *replacement,
+            });
+        }
+        if crate_level && WORKSPACE_FIELDS.contains(&key.as_str()) {
+            issues.push(ConfigIssue::IgnoredInCrate { key: key.clone() });
+        }
+    }
+    issues
+}
+
+/// The field closest to `key`, if it is close enough to be a typo
+fn suggest_field(key: &str, fields: &[&'static str]) -> Option<&'static str> {
+    let normalized = key.to_lowercase().replace('_', "-");
+    fields
+        .iter()
+        .map(|field| (edit_distance(&normalized, field), *field))
+        .filter(|(distance, _)| *distance <= 3)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, field)| field)
+}
+
+/// Levenshtein distance between `a` and `b`
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut prev = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = prev + if ca == *cb { 0 } else { 1 };
+            prev = row[j + 1];
+            row[j + 1] = substitution.min(prev + 1).min(row[j] + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// A configuration field and the layers setting it
+#[derive(Debug, Clone)]
+pub struct FieldExplanation<'l> {
+    pub key: String,
+    /// The effective value and where it came from
+    pub value: (toml::Value, &'l ConfigOrigin),
+    /// Values from lower precedence layers, highest precedence first
+    pub shadowed: Vec<(toml::Value, &'l ConfigOrigin)>,
+}
+
+/// Trace every field set in `layers` back to the layer it came from.
+pub fn explain(layers: &[ConfigLayer]) -> Result<Vec<FieldExplanation<'_>>, FatalError> {
+    let mut fields: std::collections::BTreeMap<String, Vec<(toml::Value, &ConfigOrigin)>> =
+        Default::default();
+    for layer in layers {
+        let table = match toml::Value::try_from(&layer.config)? {
+            toml::Value::Table(table) => table,
+            _ => unreachable!("`Config` is a struct"),
+        };
+        for (key, value) in table {
+            if key == "profile" || key == "include" {
+                // Selected profiles and included files are layers of their own
+                continue;
+            }
+            fields.entry(key).or_default().push((value, &layer.origin));
+        }
+    }
+
+    let explanations = fields
+        .into_iter()
+        .map(|(key, mut values)| {
+            let value = values.pop().expect("only set fields are tracked");
+            values.reverse();
+            FieldExplanation {
+                key,
+                value,
+                shadowed: values,
+            }
+        })
+        .collect();
+    Ok(explanations)
+}
+
+/// Find the 1-based line setting `key` in `table` (`None` for the top-level table).
+fn find_key_line(content: &str, table: Option<&str>, key: &str) -> Option<usize> {
+    let mut current: Option<String> = None;
+    for (index, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.starts_with('[') {
+            let header = line
+                .trim_start_matches('[')
+                .split(']')
+                .next()
+                .unwrap_or_default();
+            let header: String = header
+                .split('.')
+                .map(|part| part.trim().trim_matches('"'))
+                .collect::<Vec<_>>()
+                .join(".");
+            current = Some(header);
+            continue;
+        }
+        if current.as_deref() != table {
+            continue;
+        }
+        let mut parts = line.splitn(2, '=');
+        let name = parts.next().unwrap_or_default().trim().trim_matches('"');
+        if parts.next().is_some() && name == key {
+            return Some(index + 1);
+        }
+    }
+    None
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
 
+    #[allow(unused_imports)] // Not being detected
+    use assert_fs::prelude::*;
+
     mod resolve_config {
         use super::*;
 
@@ -675,5 +1834,420 @@ mod test {
             let release_config = resolve_config(Path::new("."), Path::new("Cargo.toml")).unwrap();
             assert!(release_config.sign_commit());
         }
+
+        #[test]
+        fn workspace_metadata() {
+            let temp = assert_fs::TempDir::new().unwrap();
+            temp.copy_from("tests/fixtures/pure_ws", &["**"]).unwrap();
+            temp.child("Cargo.toml")
+                .write_str(
+                    r#"[workspace]
+members = ["a", "b", "c"]
+
+[workspace.metadata.release]
+sign-commit = true
+push-remote = "upstream"
+"#,
+                )
+                .unwrap();
+            let crate_manifest = temp.child("a/Cargo.toml");
+            let mut manifest = std::fs::read_to_string(crate_manifest.path()).unwrap();
+            manifest.push_str("\n[package.metadata.release]\npush-remote = \"fork\"\n");
+            crate_manifest.write_str(&manifest).unwrap();
+
+            let ws_config = resolve_workspace_config(temp.path()).unwrap();
+            assert!(ws_config.sign_commit());
+            assert_eq!(ws_config.push_remote(), "upstream");
+
+            let crate_config = resolve_config(temp.path(), crate_manifest.path()).unwrap();
+            assert!(crate_config.sign_commit());
+            assert_eq!(crate_config.push_remote(), "fork");
+
+            temp.close().unwrap();
+        }
+    }
+
+    mod env_layers {
+        use super::*;
+
+        fn vars(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+            vars.iter()
+                .map(|(k, v)| ((*k).to_owned(), (*v).to_owned()))
+                .collect()
+        }
+
+        #[test]
+        fn values() {
+            let layers = env_layers(vars(&[
+                ("CARGO_RELEASE_SIGN_TAG", "true"),
+                ("CARGO_RELEASE_PUSH_REMOTE", "upstream"),
+                ("CARGO_RELEASE_TAG_NAME", "1.0"),
+                (
+                    "CARGO_RELEASE_PUSH_OPTIONS",
+                    r#"["ci.skip", "merge_request.create"]"#,
+                ),
+                ("CARGO_RELEASE_NOT_A_FIELD", "true"),
+                ("PATH", "/usr/bin"),
+            ]))
+            .unwrap();
+            assert_eq!(layers.len(), 4);
+            assert_eq!(
+                layers[0].origin,
+                ConfigOrigin::Env("CARGO_RELEASE_PUSH_OPTIONS".to_owned())
+            );
+
+            let config = merge_layers(&layers);
+            assert!(config.sign_tag());
+            assert_eq!(config.push_remote(), "upstream");
+            assert_eq!(config.tag_name(), "1.0");
+            assert_eq!(
+                config.push_options(),
+                &["ci.skip".to_owned(), "merge_request.create".to_owned()][..]
+            );
+        }
+
+        #[test]
+        fn invalid_value() {
+            let err = env_layers(vars(&[("CARGO_RELEASE_SIGN_TAG", "maybe")]));
+            assert!(err.is_err());
+        }
+
+        #[test]
+        fn file_only_fields() {
+            let err = env_layers(vars(&[("CARGO_RELEASE_INCLUDE", r#"["org.toml"]"#)]));
+            assert!(matches!(err, Err(FatalError::UnsupportedEnvConfig(_, _))));
+        }
+    }
+
+    mod with_profile {
+        use super::*;
+
+        #[test]
+        fn after_defining_file() {
+            let profile = |config: Config| {
+                let mut profile = BTreeMap::new();
+                profile.insert("nightly".to_owned(), config);
+                Some(profile)
+            };
+            let workspace = ConfigLayer {
+                origin: ConfigOrigin::file(PathBuf::from("release.toml")),
+                config: Config {
+                    profile: profile(Config {
+                        registry: Some("private".to_owned()),
+                        push_remote: Some("nightly".to_owned()),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                },
+            };
+            let krate = ConfigLayer {
+                origin: ConfigOrigin::manifest(PathBuf::from("a/Cargo.toml")),
+                config: Config {
+                    registry: Some("internal".to_owned()),
+                    ..Default::default()
+                },
+            };
+
+            let layers = with_profile(vec![workspace, krate], "nightly");
+            assert_eq!(layers.len(), 3);
+            assert_eq!(
+                layers[1].origin,
+                ConfigOrigin::File {
+                    path: PathBuf::from("release.toml"),
+                    table: Some("profile.nightly".to_owned()),
+                }
+            );
+            let config = merge_layers(&layers);
+            assert_eq!(config.registry(), Some("internal"));
+            assert_eq!(config.push_remote(), "nightly");
+        }
+    }
+
+    mod check_profile {
+        use super::*;
+
+        #[test]
+        fn defined_by_a_crate() {
+            let temp = assert_fs::TempDir::new().unwrap();
+            temp.child("Cargo.toml")
+                .write_str("[workspace]\nmembers = [\"a\", \"b\"]\n")
+                .unwrap();
+            temp.child("a/Cargo.toml")
+                .write_str("[package]\nname = \"a\"\nversion = \"0.1.0\"\n")
+                .unwrap();
+            temp.child("b/Cargo.toml")
+                .write_str(
+                    "[package]\nname = \"b\"\nversion = \"0.1.0\"\n\n\
+                     [package.metadata.release.profile.nightly]\nregistry = \"private\"\n",
+                )
+                .unwrap();
+
+            let a = temp.child("a/Cargo.toml");
+            let b = temp.child("b/Cargo.toml");
+            let members = [a.path(), b.path()];
+            check_profile(temp.path(), &members, false, None, "nightly").unwrap();
+            assert!(matches!(
+                check_profile(temp.path(), &members, false, None, "final"),
+                Err(FatalError::UnknownProfile(_))
+            ));
+            assert!(check_profile(temp.path(), &members[..1], false, None, "nightly").is_err());
+
+            temp.close().unwrap();
+        }
+    }
+
+    mod include {
+        use super::*;
+
+        #[test]
+        fn before_own_values() {
+            let temp = assert_fs::TempDir::new().unwrap();
+            temp.child("org.toml")
+                .write_str("sign-commit = true\npush-remote = \"org\"\n")
+                .unwrap();
+            temp.child("repo/release.toml")
+                .write_str("include = [\"../org.toml\"]\npush-remote = \"repo\"\n")
+                .unwrap();
+
+            let layers = file_layers(temp.child("repo/release.toml").path().to_owned()).unwrap();
+            assert_eq!(layers.len(), 2);
+            assert_eq!(
+                layers[0].origin,
+                ConfigOrigin::file(temp.child("repo/../org.toml").path().to_owned())
+            );
+
+            let config = merge_layers(&layers);
+            assert!(config.sign_commit());
+            assert_eq!(config.push_remote(), "repo");
+
+            temp.close().unwrap();
+        }
+
+        #[test]
+        fn cycle() {
+            let temp = assert_fs::TempDir::new().unwrap();
+            temp.child("a.toml")
+                .write_str("include = [\"b.toml\"]")
+                .unwrap();
+            temp.child("b.toml")
+                .write_str("include = [\"a.toml\"]")
+                .unwrap();
+
+            let err = file_layers(temp.child("a.toml").path().to_owned()).unwrap_err();
+            assert!(matches!(err, FatalError::ConfigIncludeCycle(_)));
+
+            let diagnostics = check_source(
+                &ConfigOrigin::file(temp.child("a.toml").path().to_owned()),
+                false,
+            );
+            assert_eq!(diagnostics.len(), 1);
+            assert_eq!(
+                diagnostics[0].issue,
+                ConfigIssue::IncludeCycle("a.toml".to_owned())
+            );
+
+            temp.close().unwrap();
+        }
+
+        #[test]
+        fn missing() {
+            let temp = assert_fs::TempDir::new().unwrap();
+            temp.child("a.toml")
+                .write_str("include = [\"b.toml\"]")
+                .unwrap();
+
+            let err = file_layers(temp.child("a.toml").path().to_owned()).unwrap_err();
+            assert!(matches!(err, FatalError::ConfigIncludeNotFound(_, _)));
+
+            temp.close().unwrap();
+        }
+    }
+
+    mod check {
+        use super::*;
+
+        fn check(content: &str, crate_level: bool) -> Vec<ConfigIssue> {
+            let table: toml::value::Table = toml::from_str(content).unwrap();
+            check_table(&table, crate_level)
+        }
+
+        #[test]
+        fn valid() {
+            assert_eq!(
+                check("sign-commit = true\ntag-name = \"v{{version}}\"", true),
+                []
+            );
+        }
+
+        #[test]
+        fn unknown_key() {
+            assert_eq!(
+                check("sign_comit = true\nfrobnicate = 1", false),
+                [
+                    ConfigIssue::UnknownKey {
+                        key: "frobnicate".to_owned(),
+                        suggestion: None,
+                    },
+                    ConfigIssue::UnknownKey {
+                        key: "sign_comit".to_owned(),
+                        suggestion: Some("sign-commit"),
+                    },
+                ]
+            );
+        }
+
+        #[test]
+        fn invalid_value() {
+            let issues = check("sign-commit = \"yes\"", false);
+            assert_eq!(issues.len(), 1);
+            assert!(issues[0].is_error());
+            assert_eq!(issues[0].key(), Some("sign-commit"));
+        }
+
+        #[test]
+        fn deprecated() {
+            assert_eq!(
+                check("pro-release-commit-message = \"next\"", false),
+                [ConfigIssue::Deprecated {
+                    key: "pro-release-commit-message".to_owned(),
+                    replacement: Some("post-release-commit-message"),
+                }]
+            );
+            assert_eq!(
+                check("consolidate-pushes = true", false)[0].to_string(),
+                "`consolidate-pushes` is deprecated and has no effect"
+            );
+        }
+
+        #[test]
+        fn ignored_in_crate() {
+            assert_eq!(check("push-remote = \"upstream\"", false), []);
+            assert_eq!(
+                check("push-remote = \"upstream\"", true),
+                [ConfigIssue::IgnoredInCrate {
+                    key: "push-remote".to_owned(),
+                }]
+            );
+        }
+
+        #[test]
+        fn distance() {
+            assert_eq!(edit_distance("", "abc"), 3);
+            assert_eq!(edit_distance("kitten", "sitting"), 3);
+            assert_eq!(edit_distance("sign-tag", "sign-tag"), 0);
+        }
+    }
+
+    mod push_targets {
+        use super::*;
+
+        #[test]
+        fn default_to_push_remote() {
+            let config: Config = toml::from_str("push-remote = \"upstream\"").unwrap();
+            let targets = config.push_targets();
+            assert_eq!(targets.len(), 1);
+            assert_eq!(targets[0].remote, "upstream");
+            assert_eq!(targets[0].refspec(), "HEAD");
+        }
+
+        #[test]
+        fn remote_branch() {
+            let config: Config = toml::from_str(
+                r#"
+push-remote = "upstream"
+push-targets = [
+  {remote = "origin"},
+  {remote = "mirror", refspec = "+HEAD:refs/heads/1.x", push-options = ["ci.skip"]},
+  {remote = "github", refspec = "release/1.x", disable-tags = true},
+]
+"#,
+            )
+            .unwrap();
+            let targets = config.push_targets();
+            let branches: Vec<_> = targets
+                .iter()
+                .map(|t| t.remote_branch("release/1.x"))
+                .collect();
+            assert_eq!(branches, ["release/1.x", "1.x", "release/1.x"]);
+            assert!(!targets[0].disable_tags());
+            assert!(targets[2].disable_tags());
+            assert_eq!(
+                targets[1].push_options.as_deref(),
+                Some(&["ci.skip".to_owned()][..])
+            );
+        }
+    }
+
+    mod schema {
+        use super::*;
+
+        #[test]
+        fn documents_every_field() {
+            let root = schema();
+            let properties = &root.schema.object.as_ref().unwrap().properties;
+            assert_eq!(properties.len(), config_fields().len());
+            for (name, property) in properties {
+                let property = property.clone().into_object();
+                let metadata = property.metadata.unwrap_or_default();
+                assert!(metadata.description.is_some(), "`{}` is undocumented", name);
+                assert_eq!(metadata.default, None, "`{}` has a default", name);
+            }
+        }
+    }
+
+    mod explain {
+        use super::*;
+
+        #[test]
+        fn shadowed() {
+            let layers = vec![
+                ConfigLayer {
+                    origin: ConfigOrigin::file(PathBuf::from("release.toml")),
+                    config: Config {
+                        sign_commit: Some(false),
+                        push_remote: Some("upstream".to_owned()),
+                        ..Default::default()
+                    },
+                },
+                ConfigLayer {
+                    origin: ConfigOrigin::Args,
+                    config: Config {
+                        sign_commit: Some(true),
+                        ..Default::default()
+                    },
+                },
+            ];
+            let fields = explain(&layers).unwrap();
+            assert_eq!(fields.len(), 2);
+
+            assert_eq!(fields[0].key, "push-remote");
+            assert_eq!(fields[0].value.1, &layers[0].origin);
+            assert!(fields[0].shadowed.is_empty());
+
+            assert_eq!(fields[1].key, "sign-commit");
+            assert_eq!(fields[1].value.0, toml::Value::Boolean(true));
+            assert_eq!(fields[1].value.1, &ConfigOrigin::Args);
+            assert_eq!(fields[1].shadowed.len(), 1);
+            assert_eq!(fields[1].shadowed[0].0, toml::Value::Boolean(false));
+        }
+
+        #[test]
+        fn find_line() {
+            let content = r#"
+sign-commit = true
+
+[package]
+name = "foo"
+
+[package.metadata.release]
+"sign-tag" = false
+"#;
+            assert_eq!(find_key_line(content, None, "sign-commit"), Some(2));
+            assert_eq!(find_key_line(content, None, "name"), None);
+            assert_eq!(
+                find_key_line(content, Some("package.metadata.release"), "sign-tag"),
+                Some(8)
+            );
+        }
     }
 }
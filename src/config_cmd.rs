@@ -0,0 +1,137 @@
+//! `cargo release config`
+
+use std::path::Path;
+
+use crate::args::ConfigOpt;
+use crate::config;
+use crate::error::FatalError;
+
+/// Print the configuration of each selected crate
+pub fn run(opt: &ConfigOpt) -> Result<i32, FatalError> {
+    if opt.schema {
+        println!("{}", serde_json::to_string_pretty(&config::schema())?);
+        return Ok(0);
+    }
+
+    let ws_meta = opt.manifest.metadata().exec().map_err(FatalError::from)?;
+    if opt.check {
+        return check(opt, &ws_meta);
+    }
+
+    let (selected_pkgs, _) = opt.workspace.partition_packages(&ws_meta);
+    if selected_pkgs.is_empty() {
+        log::info!("No packages selected.");
+        return Ok(0);
+    }
+    if let Some(profile) = opt.profile.as_deref() {
+        let manifest_paths: Vec<_> = ws_meta
+            .packages
+            .iter()
+            .filter(|p| ws_meta.workspace_members.contains(&p.id))
+            .map(|p| p.manifest_path.as_path())
+            .collect();
+        config::check_profile(
+            &ws_meta.workspace_root,
+            &manifest_paths,
+            opt.isolated,
+            opt.custom_config.as_deref().map(Path::new),
+            profile,
+        )?;
+    }
+
+    for (i, pkg) in selected_pkgs.iter().enumerate() {
+        let layers = config::resolve_layers(
+            &ws_meta.workspace_root,
+            Some(pkg.manifest_path.as_path()),
+            opt.isolated,
+            opt.custom_config.as_deref().map(Path::new),
+            opt.profile.as_deref(),
+            &opt.config,
+        )?;
+
+        if i != 0 {
+            println!();
+        }
+        println!("# {}", pkg.name);
+        if opt.explain {
+            print_explanation(&layers)?;
+        } else {
+            let config = config::merge_layers(&layers);
+            print!("{}", toml::to_string(&config)?);
+        }
+    }
+
+    Ok(0)
+}
+
+fn check(opt: &ConfigOpt, ws_meta: &cargo_metadata::Metadata) -> Result<i32, FatalError> {
+    let manifest_paths: Vec<_> = ws_meta
+        .packages
+        .iter()
+        .filter(|p| ws_meta.workspace_members.contains(&p.id))
+        .map(|p| p.manifest_path.as_path())
+        .collect();
+    let mut sources = config::workspace_sources(&ws_meta.workspace_root, &manifest_paths);
+    if let Some(custom_config) = opt.custom_config.as_ref() {
+        sources.push((
+            config::ConfigOrigin::File {
+                path: custom_config.into(),
+                table: None,
+            },
+            false,
+        ));
+    }
+
+    let mut errors = 0;
+    let mut warnings = 0;
+    for (origin, crate_level) in &sources {
+        for diagnostic in config::check_source(origin, *crate_level) {
+            let location = match diagnostic.issue.key() {
+                Some(key) => source(&diagnostic.origin, key),
+                None => diagnostic.origin.to_string(),
+            };
+            if diagnostic.issue.is_error() {
+                errors += 1;
+                println!("error: {}: {}", location, diagnostic.issue);
+            } else {
+                warnings += 1;
+                println!("warning: {}: {}", location, diagnostic.issue);
+            }
+        }
+    }
+
+    println!("{} errors, {} warnings", errors, warnings);
+    Ok(if errors == 0 { 0 } else { 1 })
+}
+
+fn print_explanation(layers: &[config::ConfigLayer]) -> Result<(), FatalError> {
+    let fields = config::explain(layers)?;
+    if fields.is_empty() {
+        println!("# No fields set, defaults apply");
+        return Ok(());
+    }
+
+    for field in fields {
+        let (value, origin) = &field.value;
+        println!("{} = {}", field.key, value);
+        println!("    # from {}", source(origin, &field.key));
+        for (value, origin) in &field.shadowed {
+            println!(
+                "    # shadows {} from {}",
+                value,
+                source(origin, &field.key)
+            );
+        }
+    }
+    println!("# Unset fields use their defaults");
+    Ok(())
+}
+
+fn source(origin: &config::ConfigOrigin, key: &str) -> String {
+    match (origin, origin.find_line(key)) {
+        (config::ConfigOrigin::File { path, .. }, Some(line)) => {
+            format!("{}:{}", path.display(), line)
+        }
+        _ => origin.to_string(),
+    }
+}
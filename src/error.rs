@@ -8,7 +8,9 @@ use cargo_metadata::Error as CargoMetaError;
 use quick_error::quick_error;
 use regex::Error as RegexError;
 use semver::SemVerError;
+use serde_json::Error as JsonError;
 use toml::de::Error as TomlError;
+use toml::ser::Error as TomlSerError;
 use toml_edit::TomlError as TomlEditError;
 
 quick_error! {
@@ -27,6 +29,11 @@ quick_error! {
             from()
             source(err)
         }
+        TomlSerializeError(err: TomlSerError) {
+            display("Unable to serialize to TOML: {}", err)
+            from()
+            source(err)
+        }
         InvalidTomlEditFileFormat(err: TomlEditError) {
             display("Invalid TOML file format: {}", err)
             from()
@@ -40,8 +47,29 @@ quick_error! {
         InvalidCargoFileFormat(msg: String) {
             display("Invalid TOML file format: {}", msg)
         }
-        InvalidCargoConfigKeys {
-            display("Invalid cargo-release config item found")
+        InvalidJsonFormat(err: JsonError) {
+            display("Invalid JSON format: {}", err)
+            from()
+            source(err)
+        }
+        InvalidEnvConfig(var: String, err: TomlError) {
+            display("Invalid value for `{}`: {}", var, err)
+        }
+        UnsupportedEnvConfig(var: String, key: String) {
+            display("`{}` can't be set from the environment (`{}`), only in config files", key, var)
+        }
+        ConfigIncludeNotFound(path: PathBuf, from: PathBuf) {
+            display("Unable to find config file {} included from {}", path.display(), from.display())
+        }
+        ConfigIncludeCycle(path: PathBuf) {
+            display("Config file {} includes itself", path.display())
+        }
+        UnknownProfile(name: String) {
+            display("Profile `{}` is not defined in any config file", name)
+        }
+        InvalidCargoConfigKeys(path: PathBuf, err: TomlError) {
+            display("Invalid cargo-release config in {}: {}\nRun `cargo release config --check` for details", path.display(), err)
+            source(err)
         }
         SemVerError(err: SemVerError) {
             from()
@@ -73,6 +101,9 @@ quick_error! {
         UnsupportedPrereleaseVersionScheme {
             display("This version scheme is not supported by cargo-release. Use format like `pre`, `dev` or `alpha.1` for prerelease symbol")
         }
+        MissingBuildMetadata {
+            display("Snapshot release requires build metadata, set it with `-m` or the `metadata` config")
+        }
         UnsupportedVersionReq(req: String) {
             display("Support for modifying {} is currently unsupported", req)
         }
@@ -98,11 +129,26 @@ quick_error! {
         GitError {
             display("git is not found. git is required for cargo-release workflow.")
         }
+        GitCommandFailed(command: String, stderr: String) {
+            display("`{}` failed: {}", command, stderr)
+        }
         PublishTimeoutError {
             display("Timeout waiting for crate to be published.")
         }
+        InheritedVersionNotReleased(names: Vec<String>) {
+            display("Crates left out of the release inherit the `[workspace.package]` version too: {}", names.join(", "))
+        }
         DependencyVersionConflict {
             display("Dependency is configured to conflict with new version")
         }
+        LockUpdateFailed(name: String) {
+            display("Unable to update {} in Cargo.lock without network access", name)
+        }
+        TagMessageValueUnavailable(placeholder: &'static str, name: String, reason: String) {
+            display("{} in the tag message of {} is unavailable: {}", placeholder, name, reason)
+        }
+        UnrelatedLockChanges(entries: Vec<String>) {
+            display("Cargo.lock changes unrelated dependencies:\n  {}", entries.join("\n  "))
+        }
     }
 }
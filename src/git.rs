@@ -1,6 +1,6 @@
 use std::path::Path;
 use std::path::PathBuf;
-use std::process::Command;
+use std::process::{Command, Stdio};
 
 use bstr::ByteSlice;
 
@@ -18,10 +18,16 @@ pub fn fetch(dir: &Path, remote: &str, branch: &str) -> Result<(), FatalError> {
         .map_err(|_| FatalError::GitError)
 }
 
-pub fn is_behind_remote(dir: &Path, remote: &str, branch: &str) -> Result<bool, FatalError> {
+/// Whether `remote_branch` on `remote` has commits `branch` doesn't
+pub fn is_behind_remote(
+    dir: &Path,
+    remote: &str,
+    remote_branch: &str,
+    branch: &str,
+) -> Result<bool, FatalError> {
     let output = Command::new("git")
         .arg("merge-base")
-        .arg(&format!("{}/{}", remote, branch))
+        .arg(&format!("{}/{}", remote, remote_branch))
         .arg(branch)
         .current_dir(dir)
         .output()
@@ -30,13 +36,13 @@ pub fn is_behind_remote(dir: &Path, remote: &str, branch: &str) -> Result<bool,
 
     let output = Command::new("git")
         .arg("rev-parse")
-        .arg(&format!("{}/{}", remote, branch))
+        .arg(&format!("{}/{}", remote, remote_branch))
         .current_dir(dir)
         .output()
         .map_err(FatalError::from)?;
     let upstream_sha = String::from_utf8(output.stdout)?.trim().to_owned();
 
-    log::trace!("{}/{}: {}", remote, branch, upstream_sha);
+    log::trace!("{}/{}: {}", remote, remote_branch, upstream_sha);
     log::trace!("merge base: {}", base_sha);
 
     Ok(base_sha != upstream_sha)
@@ -110,45 +116,265 @@ pub fn commit_all(dir: &Path, msg: &str, sign: bool, dry_run: bool) -> Result<bo
     )
 }
 
+/// Create `name` from HEAD and switch to it
+pub fn create_branch(dir: &Path, name: &str, dry_run: bool) -> Result<bool, FatalError> {
+    call_on_path(vec!["git", "checkout", "-b", name], dir, dry_run)
+}
+
+/// Check out `branch`, discarding any changes to tracked files
+pub fn force_checkout(dir: &Path, branch: &str, dry_run: bool) -> Result<bool, FatalError> {
+    call_on_path(vec!["git", "checkout", "--force", branch], dir, dry_run)
+}
+
+pub fn delete_branch(dir: &Path, name: &str, dry_run: bool) -> Result<bool, FatalError> {
+    call_on_path(vec!["git", "branch", "-D", name], dir, dry_run)
+}
+
+/// Create tag `name`, annotated with `msg` or lightweight without it
 pub fn tag(
     dir: &Path,
     name: &str,
-    msg: &str,
+    msg: Option<&str>,
     sign: bool,
     dry_run: bool,
 ) -> Result<bool, FatalError> {
-    call_on_path(
-        vec![
-            "git",
-            "tag",
-            "-a",
-            name,
-            "-m",
-            msg,
-            if sign { "-s" } else { "" },
-        ],
-        dir,
-        dry_run,
-    )
+    let mut command = vec!["git", "tag", name];
+    if let Some(msg) = msg {
+        command.extend(&["-a", "-m", msg]);
+        if sign {
+            command.push("-s");
+        }
+    }
+    call_on_path(command, dir, dry_run)
 }
 
+/// Push `refs` to `remote` in a single `git push --atomic`, so either all of them are updated or
+/// none, logging the refs the remote rejected
 pub fn push(
     dir: &Path,
     remote: &str,
+    refs: &[String],
     options: &[String],
     dry_run: bool,
 ) -> Result<bool, FatalError> {
-    let mut command = vec!["git", "push"];
+    let command = push_command(remote, refs, options, false);
+    if dry_run {
+        log::trace!("cd {}", dir.display());
+        log::trace!("{}", command.join(" "));
+        return Ok(true);
+    }
+    run_push(dir, remote, &command)
+}
+
+/// Whether `remote` would accept `refs`, according to `git push --dry-run`, logging the refs it
+/// would reject
+pub fn can_push(
+    dir: &Path,
+    remote: &str,
+    refs: &[String],
+    options: &[String],
+) -> Result<bool, FatalError> {
+    run_push(dir, remote, &push_command(remote, refs, options, true))
+}
+
+fn push_command<'a>(
+    remote: &'a str,
+    refs: &'a [String],
+    options: &'a [String],
+    check: bool,
+) -> Vec<&'a str> {
+    let mut command = vec!["git", "push", "--atomic", "--porcelain"];
+    if check {
+        command.push("--dry-run");
+    }
     for option in options {
         command.push("--push-option");
         command.push(option.as_str());
     }
     command.push(remote);
-    call_on_path(command, dir, dry_run)
+    command.extend(refs.iter().map(String::as_str));
+    command
+}
+
+fn run_push(dir: &Path, remote: &str, command: &[&str]) -> Result<bool, FatalError> {
+    let output = Command::new(command[0])
+        .args(&command[1..])
+        .current_dir(dir)
+        .stderr(Stdio::inherit())
+        .output()
+        .map_err(FatalError::from)?;
+    let stdout = String::from_utf8(output.stdout)?;
+    for (refname, reason) in rejected_refs(&stdout) {
+        log::error!("{} was rejected by {}: {}", refname, remote, reason);
+    }
+    Ok(output.status.success())
+}
+
+/// The refs marked as rejected (`!`) in the `--porcelain` output of `git push`, with the reason
+fn rejected_refs(porcelain: &str) -> Vec<(&str, &str)> {
+    porcelain
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.splitn(3, '\t');
+            if fields.next()? != "!" {
+                return None;
+            }
+            let refspec = fields.next()?;
+            let refname = refspec.rsplit(':').next().unwrap_or(refspec);
+            Some((refname, fields.next().unwrap_or("")))
+        })
+        .collect()
+}
+
+pub fn tag_exists(dir: &Path, tag: &str) -> Result<bool, FatalError> {
+    let output = Command::new("git")
+        .arg("rev-parse")
+        .arg("--verify")
+        .arg("--quiet")
+        .arg(format!("{}^{{commit}}", tag))
+        .current_dir(dir)
+        .output()
+        .map_err(FatalError::from)?;
+    Ok(output.status.success())
+}
+
+pub fn is_ancestor(dir: &Path, rev: &str) -> Result<bool, FatalError> {
+    let output = Command::new("git")
+        .arg("merge-base")
+        .arg("--is-ancestor")
+        .arg(rev)
+        .arg("HEAD")
+        .current_dir(dir)
+        .output()
+        .map_err(FatalError::from)?;
+    Ok(output.status.success())
+}
+
+/// Content of `file`, relative to `dir`, at `rev`, or `None` if it isn't tracked there
+pub fn file_at(dir: &Path, rev: &str, file: &Path) -> Result<Option<String>, FatalError> {
+    let output = Command::new("git")
+        .arg("show")
+        .arg(format!("{}:./{}", rev, file.display()))
+        .current_dir(dir)
+        .output()
+        .map_err(FatalError::from)?;
+    if output.status.success() {
+        Ok(Some(String::from_utf8(output.stdout)?))
+    } else {
+        Ok(None)
+    }
+}
+
+pub fn tags(dir: &Path) -> Result<Vec<String>, FatalError> {
+    let output = Command::new("git")
+        .arg("tag")
+        .arg("--list")
+        .current_dir(dir)
+        .output()
+        .map_err(FatalError::from)?;
+    let tags = String::from_utf8(output.stdout)?
+        .lines()
+        .map(|l| l.trim().to_owned())
+        .filter(|l| !l.is_empty())
+        .collect();
+    Ok(tags)
+}
+
+pub fn worktree_add(dir: &Path, path: &Path, rev: &str) -> Result<bool, FatalError> {
+    call_on_path(
+        vec![
+            "git",
+            "worktree",
+            "add",
+            "--detach",
+            path.to_str().unwrap(),
+            rev,
+        ],
+        dir,
+        false,
+    )
 }
 
-pub fn push_tag(dir: &Path, remote: &str, tag: &str, dry_run: bool) -> Result<bool, FatalError> {
-    call_on_path(vec!["git", "push", remote, tag], dir, dry_run)
+pub fn worktree_remove(dir: &Path, path: &Path) -> Result<bool, FatalError> {
+    call_on_path(
+        vec![
+            "git",
+            "worktree",
+            "remove",
+            "--force",
+            path.to_str().unwrap(),
+        ],
+        dir,
+        false,
+    )
+}
+
+pub fn short_sha(dir: &Path) -> Result<String, FatalError> {
+    let output = Command::new("git")
+        .arg("rev-parse")
+        .arg("--short")
+        .arg("HEAD")
+        .current_dir(dir)
+        .output()
+        .map_err(FatalError::from)?;
+    if !output.status.success() {
+        return Err(FatalError::GitCommandFailed(
+            "git rev-parse --short HEAD".to_owned(),
+            String::from_utf8_lossy(&output.stderr).trim().to_owned(),
+        ));
+    }
+    let sha = String::from_utf8(output.stdout)?.trim().to_owned();
+    Ok(sha)
+}
+
+pub fn commit_count_since(dir: &Path, rev: &str) -> Result<String, FatalError> {
+    let output = Command::new("git")
+        .arg("rev-list")
+        .arg("--count")
+        .arg(format!("{}..HEAD", rev))
+        .arg("--")
+        .arg(".")
+        .current_dir(dir)
+        .output()
+        .map_err(FatalError::from)?;
+    let count = String::from_utf8(output.stdout)?.trim().to_owned();
+    Ok(count)
+}
+
+/// Whether `remote` has `tag`, `None` if the remote can't be reached
+pub fn remote_tag_exists(dir: &Path, remote: &str, tag: &str) -> Result<Option<bool>, FatalError> {
+    let output = Command::new("git")
+        .arg("ls-remote")
+        .arg("--exit-code")
+        .arg("--tags")
+        .arg(remote)
+        .arg(format!("refs/tags/{}", tag))
+        .current_dir(dir)
+        .output()
+        .map_err(FatalError::from)?;
+    match output.status.code() {
+        Some(0) => Ok(Some(true)),
+        Some(2) => Ok(Some(false)),
+        _ => Ok(None),
+    }
+}
+
+pub fn commit_count(dir: &Path) -> Result<String, FatalError> {
+    let output = Command::new("git")
+        .arg("rev-list")
+        .arg("--count")
+        .arg("HEAD")
+        .current_dir(dir)
+        .output()
+        .map_err(FatalError::from)?;
+    if !output.status.success() {
+        return Err(FatalError::GitCommandFailed(
+            "git rev-list --count HEAD".to_owned(),
+            String::from_utf8_lossy(&output.stderr).trim().to_owned(),
+        ));
+    }
+    let count = String::from_utf8(output.stdout)?.trim().to_owned();
+    Ok(count)
 }
 
 pub fn top_level(dir: &Path) -> Result<PathBuf, FatalError> {
@@ -171,3 +397,26 @@ pub(crate) fn git_version() -> Result<(), FatalError> {
         .map(|_| ())
         .map_err(|_| FatalError::GitError)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn atomic_push_rejection() {
+        let porcelain = "\
+To ../remote
+!\trefs/heads/master:refs/heads/master\t[rejected] (fetch first)
+!\trefs/tags/v0.1.0:refs/tags/v0.1.0\t[rejected] (atomic push failed)
+=\trefs/tags/v0.0.1:refs/tags/v0.0.1\t[up to date]
+Done
+";
+        assert_eq!(
+            rejected_refs(porcelain),
+            [
+                ("refs/heads/master", "[rejected] (fetch first)"),
+                ("refs/tags/v0.1.0", "[rejected] (atomic push failed)"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,422 @@
+//! `cargo release init`
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::args::InitOpt;
+use crate::error::FatalError;
+use crate::git;
+
+/// Tag naming shared by most of the existing tags
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct TagScheme {
+    prefix: String,
+    name: String,
+    example: String,
+    count: usize,
+}
+
+/// A `pre-release-replacements` entry
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Replacement {
+    file: &'static str,
+    search: String,
+    replace: String,
+}
+
+/// What goes into a crate's config
+#[derive(Debug, Default)]
+struct CrateInit {
+    replacements: Vec<Replacement>,
+    unpublished: bool,
+}
+
+impl CrateInit {
+    fn is_empty(&self) -> bool {
+        self.replacements.is_empty() && !self.unpublished
+    }
+}
+
+/// Write a `release.toml` for the workspace, plus one for each crate outside the workspace root
+/// that needs crate-specific settings
+pub fn run(opt: &InitOpt) -> Result<i32, FatalError> {
+    let ws_meta = opt.manifest.metadata().exec().map_err(FatalError::from)?;
+    let ws_root = ws_meta.workspace_root.as_path();
+    let members: Vec<_> = ws_meta
+        .packages
+        .iter()
+        .filter(|p| ws_meta.workspace_members.contains(&p.id))
+        .collect();
+
+    let crate_names: Vec<_> = members.iter().map(|p| p.name.as_str()).collect();
+    let tags = git::tags(ws_root)?;
+    let scheme = detect_tag_scheme(&tags, &crate_names);
+
+    let mut root_crate = None;
+    let mut files = Vec::new();
+    for pkg in &members {
+        let crate_root = pkg.manifest_path.parent().unwrap_or_else(|| Path::new("."));
+        let init = CrateInit {
+            replacements: detect_replacements(crate_root, &pkg.name),
+            unpublished: matches!(pkg.publish.as_deref(), Some([])),
+        };
+        if crate_root == ws_root {
+            root_crate = Some(init);
+        } else if !init.is_empty() {
+            files.push((crate_root.join("release.toml"), render_crate(&init)));
+        }
+    }
+    let workspace = render_workspace(scheme.as_ref(), root_crate.as_ref(), members.len() > 1);
+    files.insert(0, (ws_root.join("release.toml"), workspace));
+
+    // A dry run only prints the files, there is nothing to overwrite
+    if !opt.force && !opt.dry_run {
+        let existing: Vec<&PathBuf> = files
+            .iter()
+            .map(|(path, _)| path)
+            .filter(|path| path.exists())
+            .collect();
+        if !existing.is_empty() {
+            for path in existing {
+                log::error!(
+                    "{} already exists, pass --force to overwrite it",
+                    path.display()
+                );
+            }
+            return Ok(1);
+        }
+    }
+
+    for (path, content) in &files {
+        if opt.dry_run {
+            println!("# {}", path.display());
+            println!("{}", content);
+        } else {
+            log::info!("Writing {}", path.display());
+            fs::write(path, content)?;
+        }
+    }
+
+    Ok(0)
+}
+
+/// Find the naming most existing tags follow, e.g. `{{crate_name}}-v{{version}}`
+fn detect_tag_scheme(tags: &[String], crate_names: &[&str]) -> Option<TagScheme> {
+    let mut schemes: BTreeMap<(String, String), (usize, &str)> = BTreeMap::new();
+    for tag in tags {
+        let split = match tag
+            .char_indices()
+            .map(|(i, _)| i)
+            .find(|i| semver::Version::parse(&tag[*i..]).is_ok())
+        {
+            Some(split) => split,
+            None => continue,
+        };
+
+        let mut prefix = &tag[..split];
+        let name = if prefix.ends_with('v') {
+            prefix = &prefix[..prefix.len() - 1];
+            "{{prefix}}v{{version}}"
+        } else {
+            "{{prefix}}{{version}}"
+        };
+        let crate_name = crate_names
+            .iter()
+            .filter(|name| prefix.starts_with(*name))
+            .max_by_key(|name| name.len());
+        let prefix = match crate_name {
+            Some(crate_name) => format!("{{{{crate_name}}}}{}", &prefix[crate_name.len()..]),
+            None => prefix.to_owned(),
+        };
+
+        let entry = schemes
+            .entry((prefix, name.to_owned()))
+            .or_insert((0, tag.as_str()));
+        entry.0 += 1;
+    }
+
+    schemes
+        .into_iter()
+        .max_by_key(|(_, (count, _))| *count)
+        .map(|((prefix, name), (count, example))| TagScheme {
+            prefix,
+            name,
+            example: example.to_owned(),
+            count,
+        })
+}
+
+/// Replacements for the version strings in the crate's CHANGELOG.md and README.md
+fn detect_replacements(crate_root: &Path, crate_name: &str) -> Vec<Replacement> {
+    let mut replacements = Vec::new();
+
+    if let Ok(changelog) = fs::read_to_string(crate_root.join("CHANGELOG.md")) {
+        let header = changelog
+            .lines()
+            .find(|l| l.starts_with("## ") && l.contains("Unreleased"));
+        if let Some(header) = header {
+            replacements.push(Replacement {
+                file: "CHANGELOG.md",
+                search: "Unreleased".to_owned(),
+                replace: "{{version}}".to_owned(),
+            });
+            if header.contains("ReleaseDate") {
+                replacements.push(Replacement {
+                    file: "CHANGELOG.md",
+                    search: "ReleaseDate".to_owned(),
+                    replace: "{{date}}".to_owned(),
+                });
+            }
+            if changelog.contains("<!-- next-header -->") {
+                replacements.push(Replacement {
+                    file: "CHANGELOG.md",
+                    search: "<!-- next-header -->".to_owned(),
+                    replace: format!("<!-- next-header -->\n\n{}", header),
+                });
+            }
+        }
+    }
+
+    if let Ok(readme) = fs::read_to_string(crate_root.join("README.md")) {
+        let name = regex::escape(crate_name);
+        let forms = [
+            (
+                format!(r#"{} = "[0-9][a-z0-9\.-]*""#, name),
+                format!(r#"{} = "{{{{version}}}}""#, crate_name),
+            ),
+            (
+                format!(r#"{} = \{{ version = "[0-9][a-z0-9\.-]*""#, name),
+                format!(r#"{} = {{ version = "{{{{version}}}}""#, crate_name),
+            ),
+        ];
+        for (search, replace) in forms.iter() {
+            let found = regex::Regex::new(search)
+                .map(|re| re.is_match(&readme))
+                .unwrap_or(false);
+            if found {
+                replacements.push(Replacement {
+                    file: "README.md",
+                    search: search.replace(r"[0-9][a-z0-9\.-]*", r"[a-z0-9\.-]+"),
+                    replace: replace.clone(),
+                });
+            }
+        }
+    }
+
+    replacements
+}
+
+fn quote(s: &str) -> String {
+    toml::Value::String(s.to_owned()).to_string()
+}
+
+fn render_workspace(
+    scheme: Option<&TagScheme>,
+    root_crate: Option<&CrateInit>,
+    is_workspace: bool,
+) -> String {
+    let mut out = String::new();
+    out.push_str("# cargo-release configuration, generated by `cargo release init`\n");
+    out.push_str(
+        "# See https://github.com/sunng87/cargo-release/blob/master/docs/reference.md\n\n",
+    );
+
+    match scheme {
+        Some(scheme) => {
+            out.push_str(&format!(
+                "# Matches {} existing tag(s) like `{}`\n",
+                scheme.count, scheme.example
+            ));
+            out.push_str(&format!("tag-prefix = {}\n", quote(&scheme.prefix)));
+            out.push_str(&format!("tag-name = {}\n", quote(&scheme.name)));
+        }
+        None => {
+            out.push_str("# No version tags found, tags default to `v{{version}}` for the\n");
+            out.push_str("# workspace root crate and `{{crate_name}}-v{{version}}` otherwise\n");
+            out.push_str("#tag-prefix = \"\"\n");
+            out.push_str("#tag-name = \"{{prefix}}v{{version}}\"\n");
+        }
+    }
+
+    out.push('\n');
+    out.push_str("#sign-commit = true\n");
+    out.push_str("#sign-tag = true\n");
+    out.push_str("#push-remote = \"origin\"\n");
+    if is_workspace {
+        out.push_str("# Release all crates of the workspace in a single commit\n");
+        out.push_str("#consolidate-commits = true\n");
+    }
+
+    if let Some(root_crate) = root_crate {
+        if !root_crate.is_empty() {
+            out.push('\n');
+            if is_workspace {
+                // Every member reads the workspace `release.toml`, so these can't go there
+                out.push_str(
+                    "# For the root crate only, move to `[package.metadata.release]` in\n",
+                );
+                out.push_str("# Cargo.toml as this file applies to every crate of the workspace\n");
+                for line in render_crate(root_crate).lines() {
+                    if !line.starts_with('#') && !line.is_empty() {
+                        out.push('#');
+                    }
+                    out.push_str(line);
+                    out.push('\n');
+                }
+            } else {
+                out.push_str(&render_crate(root_crate));
+            }
+        }
+    }
+
+    out
+}
+
+fn render_crate(init: &CrateInit) -> String {
+    let mut out = String::new();
+    if init.unpublished {
+        out.push_str("# `publish = false` in Cargo.toml\n");
+        out.push_str("disable-release = true\n");
+    }
+    if !init.replacements.is_empty() {
+        if init.unpublished {
+            out.push('\n');
+        }
+        out.push_str("# Version strings found in the crate's documentation\n");
+        out.push_str("pre-release-replacements = [\n");
+        for replacement in &init.replacements {
+            out.push_str(&format!(
+                "  {{file={}, search={}, replace={}}},\n",
+                quote(replacement.file),
+                quote(&replacement.search),
+                quote(&replacement.replace)
+            ));
+        }
+        out.push_str("]\n");
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[allow(unused_imports)] // Not being detected
+    use assert_fs::prelude::*;
+
+    mod detect_tag_scheme {
+        use super::*;
+
+        fn tags(tags: &[&str]) -> Vec<String> {
+            tags.iter().map(|t| (*t).to_owned()).collect()
+        }
+
+        #[test]
+        fn crate_prefix() {
+            let scheme = detect_tag_scheme(
+                &tags(&["foo-v0.1.0", "foo-bar-v0.2.0", "foo-v0.2.0", "old-1.0.0"]),
+                &["foo", "foo-bar"],
+            )
+            .unwrap();
+            assert_eq!(scheme.prefix, "{{crate_name}}-");
+            assert_eq!(scheme.name, "{{prefix}}v{{version}}");
+            assert_eq!(scheme.count, 3);
+        }
+
+        #[test]
+        fn bare_version() {
+            let scheme =
+                detect_tag_scheme(&tags(&["0.1.0", "0.2.0-rc.1", "latest"]), &["foo"]).unwrap();
+            assert_eq!(scheme.prefix, "");
+            assert_eq!(scheme.name, "{{prefix}}{{version}}");
+            assert_eq!(scheme.count, 2);
+        }
+
+        #[test]
+        fn none() {
+            assert_eq!(detect_tag_scheme(&tags(&["latest"]), &["foo"]), None);
+        }
+    }
+
+    mod detect_replacements {
+        use super::*;
+
+        #[test]
+        fn changelog_and_readme() {
+            let temp = assert_fs::TempDir::new().unwrap();
+            temp.child("CHANGELOG.md")
+                .write_str("# Changes\n\n<!-- next-header -->\n\n## [Unreleased] - ReleaseDate\n")
+                .unwrap();
+            temp.child("README.md")
+                .write_str("```toml\n[dependencies]\nfoo-bar = \"0.3\"\n```\n")
+                .unwrap();
+
+            let replacements = detect_replacements(temp.path(), "foo-bar");
+            let found: Vec<_> = replacements
+                .iter()
+                .map(|r| (r.file, r.search.as_str()))
+                .collect();
+            assert_eq!(
+                found,
+                [
+                    ("CHANGELOG.md", "Unreleased"),
+                    ("CHANGELOG.md", "ReleaseDate"),
+                    ("CHANGELOG.md", "<!-- next-header -->"),
+                    ("README.md", r#"foo\-bar = "[a-z0-9\.-]+""#),
+                ]
+            );
+
+            let regex = regex::Regex::new(&replacements[3].search).unwrap();
+            assert!(regex.is_match("foo-bar = \"0.3.1-alpha.0\""));
+
+            temp.close().unwrap();
+        }
+    }
+
+    mod render_workspace {
+        use super::*;
+
+        fn init() -> (TagScheme, CrateInit) {
+            let scheme = TagScheme {
+                prefix: "{{crate_name}}-".to_owned(),
+                name: "{{prefix}}v{{version}}".to_owned(),
+                example: "foo-v0.1.0".to_owned(),
+                count: 1,
+            };
+            let root = CrateInit {
+                replacements: vec![Replacement {
+                    file: "CHANGELOG.md",
+                    search: "<!-- next-header -->".to_owned(),
+                    replace: "<!-- next-header -->\n\n## [Unreleased] - ReleaseDate".to_owned(),
+                }],
+                unpublished: true,
+            };
+            (scheme, root)
+        }
+
+        #[test]
+        fn single_crate() {
+            let (scheme, root) = init();
+            let content = render_workspace(Some(&scheme), Some(&root), false);
+            let config: crate::config::Config = toml::from_str(&content).unwrap();
+            assert_eq!(config.tag_prefix(true), "{{crate_name}}-");
+            assert!(config.disable_release());
+            assert_eq!(
+                config.pre_release_replacements()[0].replace,
+                root.replacements[0].replace
+            );
+        }
+
+        #[test]
+        fn root_crate_not_inherited() {
+            let (scheme, root) = init();
+            let content = render_workspace(Some(&scheme), Some(&root), true);
+            let config: crate::config::Config = toml::from_str(&content).unwrap();
+            assert_eq!(config.tag_prefix(true), "{{crate_name}}-");
+            assert!(!config.disable_release());
+            assert!(config.pre_release_replacements().is_empty());
+            assert!(content.contains("#disable-release = true\n"));
+        }
+    }
+}
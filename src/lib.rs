@@ -0,0 +1,48 @@
+//! Library behind `cargo release`, for embedding the release workflow in other tools.
+//!
+//! A release is planned for a [`release::Workspace`] and then executed step by step, with a
+//! [`release::Observer`] following the progress:
+//!
+//! ```no_run
+//! use cargo_release::release::{ReleaseOptions, ShellObserver, Workspace};
+//!
+//! let options = ReleaseOptions {
+//!     level_or_version: "minor".to_owned(),
+//!     dry_run: true,
+//!     ..Default::default()
+//! };
+//! let ws = Workspace::load(&options).unwrap();
+//! let plan = ws.plan(&options).unwrap();
+//! for pkg in &plan {
+//!     println!("{} {}", pkg.meta().name, pkg.prev_version().version_string);
+//! }
+//! let code = ws.release(&options, &plan, &mut ShellObserver).unwrap();
+//! ```
+
+// For the `FatalError` variants of `quick_error!`
+#![recursion_limit = "256"]
+
+mod api;
+pub mod args;
+mod cargo;
+mod cmd;
+pub mod config;
+pub mod config_cmd;
+pub mod error;
+mod git;
+pub mod init;
+pub mod release;
+pub mod replace;
+mod shell;
+pub mod status;
+mod version;
+
+/// Expresses what features flags should be used
+pub enum Features {
+    /// None - don't use special features
+    None,
+    /// Only use selected features
+    Selective(Vec<String>),
+    /// Use all features via `all-features`
+    All,
+}
@@ -0,0 +1,2028 @@
+//! Planning and executing a release
+
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::ffi::OsStr;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use chrono::prelude::Local;
+
+use crate::error::FatalError;
+use crate::replace::{changelog_section, do_file_replacements, Template};
+use crate::{api, cargo, cmd, config, error, git, shell, version, Features};
+
+static NOW: once_cell::sync::Lazy<String> =
+    once_cell::sync::Lazy::new(|| Local::now().format("%Y-%m-%d").to_string());
+
+/// Every dependency of a workspace member on `pkg_meta`, renamed or not
+fn find_dependents<'w>(
+    ws_meta: &'w cargo_metadata::Metadata,
+    pkg_meta: &'w cargo_metadata::Package,
+) -> impl Iterator<Item = (&'w cargo_metadata::Package, &'w cargo_metadata::Dependency)> {
+    ws_meta
+        .packages
+        .iter()
+        .filter(move |p| ws_meta.workspace_members.contains(&p.id))
+        .flat_map(move |p| {
+            p.dependencies
+                .iter()
+                .filter(move |d| d.name == pkg_meta.name)
+                .map(move |d| (p, d))
+        })
+}
+
+fn collect_dependents<'m>(
+    found: impl Iterator<Item = (&'m cargo_metadata::Package, &'m cargo_metadata::Dependency)>,
+) -> Vec<Dependency<'m>> {
+    let mut dependents: Vec<Dependency> = Vec::new();
+    for (pkg, dep) in found {
+        let rename = dep.rename.as_deref();
+        let table = match cargo::DependencyTable::of(dep) {
+            Some(table) => table,
+            None => continue,
+        };
+        // e.g. the same requirement in `dependencies` and `dev-dependencies`
+        let duplicate = dependents
+            .iter_mut()
+            .find(|d| d.pkg.id == pkg.id && *d.req == dep.req && d.rename == rename);
+        match duplicate {
+            Some(duplicate) => duplicate.tables.push(table),
+            None => dependents.push(Dependency {
+                pkg,
+                req: &dep.req,
+                rename,
+                registry: dep.source.is_some(),
+                tables: vec![table],
+            }),
+        }
+    }
+    dependents
+}
+
+fn exclude_paths<'m>(
+    ws_pkgs: &[&'m cargo_metadata::Package],
+    pkg_meta: &'m cargo_metadata::Package,
+) -> Vec<&'m Path> {
+    let base_path = pkg_meta
+        .manifest_path
+        .parent()
+        .unwrap_or_else(|| Path::new("/"));
+    ws_pkgs
+        .iter()
+        .filter_map(|p| {
+            let cur_path = p.manifest_path.parent().unwrap_or_else(|| Path::new("/"));
+            if cur_path != base_path && cur_path.starts_with(base_path) {
+                Some(cur_path)
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// The release of one crate, as planned by [`Workspace::plan`]
+pub struct PackageRelease<'m> {
+    meta: &'m cargo_metadata::Package,
+    manifest_path: &'m Path,
+    package_path: &'m Path,
+    config: config::Config,
+
+    crate_excludes: Vec<&'m Path>,
+    custom_ignore: ignore::gitignore::Gitignore,
+
+    prev_version: Version,
+    prev_tag: String,
+    prev_tag_version: Option<semver::Version>,
+    version: Option<Version>,
+    tag: Option<String>,
+    post_version: Option<Version>,
+
+    dependents: Vec<Dependency<'m>>,
+    /// Crates whose path dev-dependency on this one loses its `version`
+    dev_dependents: Vec<&'m cargo_metadata::Package>,
+
+    //dependent_version: config::DependentVersion,
+    //dependents: Vec<&'m Path>,
+    //failed_dependents: Vec<&'m Path>,
+    features: Features,
+}
+
+#[derive(Debug)]
+pub struct Version {
+    pub version: semver::Version,
+    pub version_string: String,
+}
+
+struct Dependency<'m> {
+    pkg: &'m cargo_metadata::Package,
+    req: &'m semver::VersionReq,
+    rename: Option<&'m str>,
+    /// Whether `pkg` gets the crate from a registry rather than by path
+    registry: bool,
+    /// Where the requirement is declared in `pkg`'s manifest
+    tables: Vec<cargo::DependencyTable>,
+}
+
+impl<'m> PackageRelease<'m> {
+    fn load(
+        options: &ReleaseOptions,
+        git_root: &Path,
+        ws_meta: &'m cargo_metadata::Metadata,
+        ws_pkgs: &[&'m cargo_metadata::Package],
+        pkg_meta: &'m cargo_metadata::Package,
+    ) -> Result<Option<Self>, error::FatalError> {
+        let manifest_path = pkg_meta.manifest_path.as_path();
+        let cwd = manifest_path.parent().unwrap_or_else(|| Path::new("."));
+
+        let layers = config::resolve_layers(
+            &ws_meta.workspace_root,
+            Some(manifest_path),
+            options.isolated,
+            options.custom_config.as_deref(),
+            options.profile.as_deref(),
+            &options.config,
+        )?;
+        let config = config::merge_layers(&layers);
+        if config.disable_release() {
+            log::debug!("Disabled in config, skipping {}", manifest_path.display());
+            return Ok(None);
+        }
+
+        let is_root = git_root == cwd;
+
+        let prev_version = Version {
+            version: pkg_meta.version.clone(),
+            version_string: pkg_meta.version.to_string(),
+        };
+
+        let crate_excludes = exclude_paths(ws_pkgs, pkg_meta);
+        let mut custom_ignore = ignore::gitignore::GitignoreBuilder::new(cwd);
+        if let Some(globs) = config.exclude_paths() {
+            for glob in globs {
+                custom_ignore.add_line(None, glob)?;
+            }
+        }
+        let custom_ignore = custom_ignore.build()?;
+
+        let render_tag = |version: &str| {
+            let mut template = Template {
+                prev_version: Some(&prev_version.version_string),
+                version: Some(version),
+                crate_name: Some(pkg_meta.name.as_str()),
+                ..Default::default()
+            };
+
+            let tag_prefix = config.tag_prefix(is_root);
+            let tag_prefix = template.render(tag_prefix);
+            template.prefix = Some(&tag_prefix);
+            template.render(config.tag_name())
+        };
+        let tag_pattern = render_tag("{{version}}");
+        let prev_tag = if let Some(prev_tag) = options.prev_tag_name.as_ref() {
+            // Trust the user that the tag passed in is the latest tag for the workspace and that
+            // they don't care about any changes from before this tag.
+            prev_tag.to_owned()
+        } else {
+            match find_prev_tag(cwd, &tag_pattern)? {
+                Some(prev_tag) => prev_tag,
+                // No release yet, or tags we don't recognize
+                None => render_tag(&prev_version.version_string),
+            }
+        };
+        let prev_tag_version = version::version_from_tag(&prev_tag, &tag_pattern);
+
+        // The configured metadata is only for snapshots, unlike an explicit `-m`
+        let is_snapshot = matches!(
+            version::BumpLevel::from_str(&options.level_or_version),
+            Ok(version::BumpLevel::Snapshot)
+        );
+        let metadata = options
+            .metadata
+            .as_deref()
+            .or_else(|| config.metadata().filter(|_| is_snapshot));
+        let metadata = match metadata {
+            Some(metadata) => {
+                let git_sha_short = git::short_sha(cwd)?;
+                let commit_count = git::commit_count(cwd)?;
+                let template = Template {
+                    crate_name: Some(pkg_meta.name.as_str()),
+                    date: Some(NOW.as_str()),
+                    git_sha_short: Some(&git_sha_short),
+                    commit_count: Some(&commit_count),
+                    ..Default::default()
+                };
+                Some(template.render(metadata))
+            }
+            None => None,
+        };
+
+        let mut is_pre_release = false;
+        let version = {
+            let mut potential_version = prev_version.version.clone();
+            if let Ok(bump_level) = version::BumpLevel::from_str(&options.level_or_version) {
+                // bump level
+                if bump_level.bump_version(&mut potential_version, metadata.as_ref())? {
+                    let version = potential_version;
+                    let version_string = version.to_string();
+                    is_pre_release = bump_level.is_pre_release();
+                    Some(Version {
+                        version,
+                        version_string,
+                    })
+                } else {
+                    None
+                }
+            } else {
+                // given version
+                match semver::Version::parse(&options.level_or_version)? {
+                    version if version > potential_version => {
+                        is_pre_release = version.is_prerelease();
+                        Some(Version {
+                            version,
+                            version_string: options.level_or_version.to_owned(),
+                        })
+                    }
+                    version if version == potential_version => None,
+                    _ => {
+                        return Err(error::FatalError::UnsupportedVersionReq(
+                            "Cannot release version smaller than current one".to_owned(),
+                        ));
+                    }
+                }
+            }
+        };
+        let dev_dependents_policy = config.dev_dependents();
+        let (dependents, dev_dependents) = if version.is_some() {
+            let dependents =
+                collect_dependents(find_dependents(ws_meta, pkg_meta).filter(|(_, dep)| {
+                    // A registry dependency of the same name is on a published version instead
+                    let is_path_dep = dep.source.is_none();
+                    is_path_dep
+                        && (dev_dependents_policy == config::DevDependents::Include
+                            || dep.kind != cargo_metadata::DependencyKind::Development)
+                }));
+            let mut dev_dependents: Vec<&cargo_metadata::Package> = Vec::new();
+            if dev_dependents_policy == config::DevDependents::Strip {
+                for (pkg, dep) in find_dependents(ws_meta, pkg_meta) {
+                    let is_path_dev_dep = dep.kind == cargo_metadata::DependencyKind::Development
+                        && dep.source.is_none();
+                    if is_path_dev_dep && !dev_dependents.iter().any(|p| p.id == pkg.id) {
+                        dev_dependents.push(pkg);
+                    }
+                }
+            }
+            (dependents, dev_dependents)
+        } else {
+            (Vec::new(), Vec::new())
+        };
+
+        let base = version.as_ref().unwrap_or(&prev_version);
+
+        let tag = if config.disable_tag() {
+            None
+        } else {
+            let mut template = Template {
+                prev_version: Some(&prev_version.version_string),
+                version: Some(&base.version_string),
+                crate_name: Some(pkg_meta.name.as_str()),
+                ..Default::default()
+            };
+
+            let tag_prefix = config.tag_prefix(is_root);
+            let tag_prefix = template.render(tag_prefix);
+            template.prefix = Some(&tag_prefix);
+            Some(template.render(config.tag_name()))
+        };
+
+        let post_version = if !is_pre_release && !config.no_dev_version() {
+            let post = version::dev_version(
+                &base.version,
+                config.dev_version_level(),
+                config.dev_version_ext(),
+            )?;
+            let post_string = post.to_string();
+
+            Some(Version {
+                version: post,
+                version_string: post_string,
+            })
+        } else {
+            None
+        };
+
+        let features = if config.enable_all_features() {
+            Features::All
+        } else {
+            let features = config.enable_features();
+            if features.is_empty() {
+                Features::None
+            } else {
+                Features::Selective(features.to_owned())
+            }
+        };
+
+        let pkg = PackageRelease {
+            meta: pkg_meta,
+            manifest_path,
+            package_path: cwd,
+            config,
+
+            crate_excludes,
+            custom_ignore,
+
+            prev_version,
+            prev_tag,
+            prev_tag_version,
+            version,
+            tag,
+            post_version,
+            dependents,
+            dev_dependents,
+
+            features,
+        };
+        Ok(Some(pkg))
+    }
+
+    pub fn meta(&self) -> &'m cargo_metadata::Package {
+        self.meta
+    }
+
+    /// Files of this crate changed since `prev_tag`, leaving out sub-crates and `exclude-paths`.
+    ///
+    /// Returns `None` when the changes can't be detected, e.g. because the tag is missing.
+    pub fn changed_files(&self) -> Result<Option<Vec<PathBuf>>, FatalError> {
+        let changed = match git::changed_files(self.package_path, &self.prev_tag)? {
+            Some(changed) => changed,
+            None => return Ok(None),
+        };
+        let changed = changed
+            .into_iter()
+            .filter(|p| {
+                let file_in_subcrate = self.crate_excludes.iter().any(|base| p.starts_with(base));
+                if file_in_subcrate {
+                    return false;
+                }
+                let glob_status = self.custom_ignore.matched_path_or_any_parents(p, false);
+                if glob_status.is_ignore() {
+                    log::trace!(
+                        "{}: ignoring {} due to {:?}",
+                        self.meta.name,
+                        p.display(),
+                        glob_status
+                    );
+                    return false;
+                }
+                true
+            })
+            .collect();
+        Ok(Some(changed))
+    }
+
+    pub fn manifest_path(&self) -> &'m Path {
+        self.manifest_path
+    }
+
+    pub fn package_path(&self) -> &'m Path {
+        self.package_path
+    }
+
+    pub fn config(&self) -> &config::Config {
+        &self.config
+    }
+
+    pub fn prev_version(&self) -> &Version {
+        &self.prev_version
+    }
+
+    pub fn prev_tag(&self) -> &str {
+        &self.prev_tag
+    }
+
+    /// The version released as `prev_tag`, if the tag follows the configured `tag-name`
+    pub fn prev_tag_version(&self) -> Option<&semver::Version> {
+        self.prev_tag_version.as_ref()
+    }
+
+    /// The version to release, `None` when the current version is released as-is
+    pub fn version(&self) -> Option<&Version> {
+        self.version.as_ref()
+    }
+
+    pub fn tag(&self) -> Option<&str> {
+        self.tag.as_deref()
+    }
+
+    /// The version starting the next development iteration
+    pub fn post_version(&self) -> Option<&Version> {
+        self.post_version.as_ref()
+    }
+
+    pub fn features(&self) -> &Features {
+        &self.features
+    }
+}
+
+fn update_dependent_versions(
+    pkg: &PackageRelease,
+    version: &Version,
+    ws_manifest_path: &Path,
+    dry_run: bool,
+) -> Result<(), error::FatalError> {
+    let mut dependents_failed =
+        !update_requirements(pkg, version, &pkg.dependents, ws_manifest_path, dry_run)?;
+    if !update_patches(pkg, version, ws_manifest_path, dry_run)? {
+        dependents_failed = true;
+    }
+    if dependents_failed {
+        Err(FatalError::DependencyVersionConflict)
+    } else {
+        Ok(())
+    }
+}
+
+/// Apply the `dependent-version` policy to `dependents` of `pkg`, `ws_manifest_path` being the
+/// root manifest of their workspace.
+///
+/// Returns `false` when the policy is `error` and a requirement is incompatible.
+fn update_requirements(
+    pkg: &PackageRelease,
+    version: &Version,
+    dependents: &[Dependency],
+    ws_manifest_path: &Path,
+    dry_run: bool,
+) -> Result<bool, FatalError> {
+    let new_version_string = version.version_string.as_str();
+    let mut dependents_failed = false;
+    for dep in dependents {
+        let dep_name = match dep.rename {
+            Some(rename) => format!("{} (as {})", pkg.meta.name, rename),
+            None => pkg.meta.name.clone(),
+        };
+        match pkg.config.dependent_version() {
+            config::DependentVersion::Ignore => (),
+            config::DependentVersion::Warn => {
+                if !dep.req.matches(&version.version) {
+                    log::warn!(
+                        "{}'s dependency on {} `{}` is incompatible with {}",
+                        dep.pkg.name,
+                        dep_name,
+                        dep.req,
+                        new_version_string
+                    );
+                }
+            }
+            config::DependentVersion::Error => {
+                if !dep.req.matches(&version.version) {
+                    log::warn!(
+                        "{}'s dependency on {} `{}` is incompatible with {}",
+                        dep.pkg.name,
+                        dep_name,
+                        dep.req,
+                        new_version_string
+                    );
+                    dependents_failed = true;
+                }
+            }
+            config::DependentVersion::Fix => {
+                if !dep.req.matches(&version.version) {
+                    let new_req = version::set_requirement(dep.req, &version.version)?;
+                    if let Some(new_req) = new_req {
+                        log::info!(
+                            "Fixing {}'s dependency on {} to `{}` (from `{}`)",
+                            dep.pkg.name,
+                            dep_name,
+                            new_req,
+                            dep.req
+                        );
+                        if !dry_run {
+                            set_dependency_version(
+                                dep,
+                                &pkg.meta.name,
+                                &new_req,
+                                ws_manifest_path,
+                            )?;
+                        }
+                    }
+                }
+            }
+            config::DependentVersion::Upgrade => {
+                let new_req = version::set_requirement(dep.req, &version.version)?;
+                if let Some(new_req) = new_req {
+                    log::info!(
+                        "Upgrading {}'s dependency on {} to `{}` (from `{}`)",
+                        dep.pkg.name,
+                        dep_name,
+                        new_req,
+                        dep.req
+                    );
+                    if !dry_run {
+                        set_dependency_version(dep, &pkg.meta.name, &new_req, ws_manifest_path)?;
+                    }
+                }
+            }
+        }
+    }
+    Ok(!dependents_failed)
+}
+
+/// Apply the `dependent-version` policy to `[patch]` and `[replace]` entries pointing at `pkg`.
+///
+/// Returns `false` when the policy is `error` and an entry is stale.
+fn update_patches(
+    pkg: &PackageRelease,
+    version: &Version,
+    ws_manifest_path: &Path,
+    dry_run: bool,
+) -> Result<bool, FatalError> {
+    let policy = pkg.config.dependent_version();
+    if policy == config::DependentVersion::Ignore {
+        return Ok(true);
+    }
+
+    let new_version_string = version.version_string.as_str();
+    let mut succeeded = true;
+    for patch in cargo::find_patches(ws_manifest_path, &pkg.meta.name, pkg.package_path)? {
+        let old = match patch.version.as_deref() {
+            Some(old) => old,
+            // Not tied to a version
+            None => continue,
+        };
+        let (stale, fixed) = match patch.table {
+            cargo::PatchTable::Patch(_) => {
+                let req = semver::VersionReq::parse(old).map_err(|_| {
+                    FatalError::InvalidCargoFileFormat(format!(
+                        "Invalid version requirement `{}` for {} in {}",
+                        old, patch.key, patch.table
+                    ))
+                })?;
+                (
+                    !req.matches(&version.version),
+                    version::set_requirement(&req, &version.version)?,
+                )
+            }
+            cargo::PatchTable::Replace => {
+                if old == new_version_string {
+                    (false, None)
+                } else {
+                    (true, Some(new_version_string.to_owned()))
+                }
+            }
+        };
+
+        let update = match policy {
+            config::DependentVersion::Ignore => false,
+            config::DependentVersion::Warn | config::DependentVersion::Error => {
+                if stale {
+                    log::warn!(
+                        "{} entry for {} `{}` is incompatible with {}",
+                        patch.table,
+                        patch.key,
+                        old,
+                        new_version_string
+                    );
+                    if policy == config::DependentVersion::Error {
+                        succeeded = false;
+                    }
+                }
+                false
+            }
+            config::DependentVersion::Fix => stale,
+            config::DependentVersion::Upgrade => fixed.is_some(),
+        };
+        if !update {
+            continue;
+        }
+
+        if pkg.config.remove_patches() {
+            log::info!("Removing {} entry for {}", patch.table, patch.key);
+            if !dry_run {
+                cargo::set_patch_version(ws_manifest_path, &patch, None)?;
+            }
+        } else if let Some(fixed) = fixed {
+            log::info!(
+                "Updating {} entry for {} to `{}` (from `{}`)",
+                patch.table,
+                patch.key,
+                fixed,
+                old
+            );
+            if !dry_run {
+                cargo::set_patch_version(ws_manifest_path, &patch, Some(&fixed))?;
+            }
+        }
+    }
+    Ok(succeeded)
+}
+
+/// Update `dep`'s requirement on crate `name` in each table declaring it, or in
+/// `[workspace.dependencies]` for entries inheriting from it
+fn set_dependency_version(
+    dep: &Dependency,
+    name: &str,
+    req: &str,
+    ws_manifest_path: &Path,
+) -> Result<(), FatalError> {
+    let key = dep.rename.unwrap_or(name);
+    let manifest_path = dep.pkg.manifest_path.as_path();
+    for table in &dep.tables {
+        if dep.registry {
+            cargo::set_registry_dependency_version(
+                manifest_path,
+                ws_manifest_path,
+                table,
+                key,
+                req,
+            )?;
+        } else {
+            cargo::set_dependency_version(manifest_path, ws_manifest_path, table, key, req)?;
+        }
+    }
+    Ok(())
+}
+
+/// Compare the public API at the previous tag with the working tree.
+///
+/// Returns `false` when items were removed but the new version is semver compatible with the
+/// previous release, or when the check can't be run.
+fn check_api(pkg: &PackageRelease, ws_meta: &cargo_metadata::Metadata) -> Result<bool, FatalError> {
+    let crate_name = pkg.meta.name.as_str();
+    let version = match pkg.version.as_ref() {
+        Some(version) => version,
+        None => return Ok(true),
+    };
+    let lib_name = match pkg
+        .meta
+        .targets
+        .iter()
+        .find(|t| t.kind.iter().any(|k| k == "lib"))
+    {
+        Some(lib) => lib.name.as_str(),
+        None => {
+            log::debug!("Not checking the API of {}, it has no library", crate_name);
+            return Ok(true);
+        }
+    };
+    let cwd = pkg.package_path;
+    if !git::tag_exists(cwd, &pkg.prev_tag)? {
+        log::error!(
+            "Cannot check the API of {} because tag {} is missing. Try setting `--prev-tag-name <TAG>`, or disable `check-api` for this release.",
+            crate_name,
+            pkg.prev_tag
+        );
+        return Ok(false);
+    }
+
+    log::info!(
+        "Checking the API of {} against {}",
+        crate_name,
+        pkg.prev_tag
+    );
+    let toolchain = pkg.config.check_api_toolchain();
+    let git_root = git::top_level(cwd)?;
+    let worktree = std::env::temp_dir().join(format!(
+        "cargo-release-{}-{}",
+        crate_name,
+        std::process::id()
+    ));
+    if !git::worktree_add(&git_root, &worktree, &pkg.prev_tag)? {
+        log::error!(
+            "Cannot check the API of {}, failed to check out {}",
+            crate_name,
+            pkg.prev_tag
+        );
+        return Ok(false);
+    }
+    let prev_manifest_path = worktree.join(
+        pkg.manifest_path
+            .strip_prefix(&git_root)
+            .unwrap_or(pkg.manifest_path),
+    );
+    let prev = (|| {
+        let prev_api = api::public_api(
+            &prev_manifest_path,
+            lib_name,
+            &pkg.features,
+            &worktree.join("target"),
+            toolchain,
+        )?;
+        let prev_version = cargo::parse_cargo_config(&prev_manifest_path)?
+            .get("package")
+            .and_then(|p| p.get("version"))
+            .and_then(|v| v.as_str())
+            .and_then(|v| semver::Version::parse(v).ok());
+        Ok::<_, FatalError>((prev_api, prev_version))
+    })();
+    git::worktree_remove(&git_root, &worktree)?;
+    let (prev_api, prev_version) = prev?;
+    let api = api::public_api(
+        pkg.manifest_path,
+        lib_name,
+        &pkg.features,
+        &ws_meta.target_directory,
+        toolchain,
+    )?;
+    let (prev_api, api) = match (prev_api, api) {
+        (Some(prev_api), Some(api)) => (prev_api, api),
+        _ => {
+            log::error!(
+                "Cannot check the API of {}, generating rustdoc JSON with the `{}` toolchain failed",
+                crate_name,
+                toolchain
+            );
+            return Ok(false);
+        }
+    };
+
+    let breaking = api::breaking_changes(&prev_api, &api);
+    if breaking.is_empty() {
+        return Ok(true);
+    }
+    let prev_version = prev_version.unwrap_or_else(|| pkg.prev_version.version.clone());
+    if !version::is_compatible(&prev_version, &version.version) {
+        log::debug!(
+            "{} removes public items since {}: {:#?}",
+            crate_name,
+            pkg.prev_tag,
+            breaking
+        );
+        return Ok(true);
+    }
+
+    log::error!(
+        "{} {} is semver compatible with {} but removes public items:",
+        crate_name,
+        version.version_string,
+        prev_version
+    );
+    for item in breaking {
+        log::error!("  {}", item);
+    }
+    log::error!(
+        "Release at least a `{}` level to signal the breaking change.",
+        version::breaking_level(&prev_version)
+            .to_string()
+            .to_lowercase()
+    );
+    Ok(false)
+}
+
+/// The tag of the highest released version reachable from HEAD, among the tags matching
+/// `tag_pattern`
+fn find_prev_tag(dir: &Path, tag_pattern: &str) -> Result<Option<String>, FatalError> {
+    let mut tags: Vec<_> = git::tags(dir)?
+        .into_iter()
+        .filter_map(|tag| version::version_from_tag(&tag, tag_pattern).map(|v| (v, tag)))
+        .collect();
+    tags.sort_by(|(a, _), (b, _)| b.cmp(a));
+    for (_, tag) in tags {
+        if git::is_ancestor(dir, &tag)? {
+            return Ok(Some(tag));
+        }
+    }
+    Ok(None)
+}
+
+/// What to release and how, the library equivalent of the `cargo release` arguments
+#[derive(Debug, Clone)]
+pub struct ReleaseOptions {
+    /// `Cargo.toml` of the workspace, by default found from the current directory
+    pub manifest_path: Option<PathBuf>,
+    /// Crates to release, by default the root crate or all members of a virtual workspace
+    pub packages: Vec<String>,
+    /// Release all members of the workspace
+    pub workspace: bool,
+    /// Crates not to release
+    pub exclude: Vec<String>,
+    /// Release level (e.g. `minor`, `rc`) or version
+    pub level_or_version: String,
+    /// Semver metadata, overriding the `metadata` config
+    pub metadata: Option<String>,
+    /// Custom config file, applied after the implicit ones
+    pub custom_config: Option<PathBuf>,
+    /// Ignore the implicit config files
+    pub isolated: bool,
+    /// Apply the `[profile.<name>]` tables of the config files
+    pub profile: Option<String>,
+    /// Config overriding all config files
+    pub config: config::Config,
+    /// Token to use for `cargo publish`
+    pub token: Option<String>,
+    /// Only log what would be done
+    pub dry_run: bool,
+    /// Don't ask for confirmation before releasing
+    pub no_confirm: bool,
+    /// Tag of the previous release, instead of looking it up
+    pub prev_tag_name: Option<String>,
+}
+
+impl Default for ReleaseOptions {
+    fn default() -> Self {
+        ReleaseOptions {
+            manifest_path: None,
+            packages: Vec::new(),
+            workspace: false,
+            exclude: Vec::new(),
+            level_or_version: "release".to_owned(),
+            metadata: None,
+            custom_config: None,
+            isolated: false,
+            profile: None,
+            config: config::Config::default(),
+            token: None,
+            dry_run: false,
+            no_confirm: false,
+            prev_tag_name: None,
+        }
+    }
+}
+
+/// A cargo workspace along with its release configuration
+pub struct Workspace {
+    meta: cargo_metadata::Metadata,
+    config: config::Config,
+    git_root: PathBuf,
+}
+
+impl Workspace {
+    /// Load the workspace selected by `options.manifest_path`
+    pub fn load(options: &ReleaseOptions) -> Result<Self, FatalError> {
+        let mut metadata = cargo_metadata::MetadataCommand::new();
+        if let Some(manifest_path) = options.manifest_path.as_ref() {
+            metadata.manifest_path(manifest_path);
+        }
+        let meta = metadata.exec().map_err(FatalError::from)?;
+        if let Some(profile) = options.profile.as_deref() {
+            let manifest_paths: Vec<_> = meta
+                .packages
+                .iter()
+                .filter(|p| meta.workspace_members.contains(&p.id))
+                .map(|p| p.manifest_path.as_path())
+                .collect();
+            config::check_profile(
+                &meta.workspace_root,
+                &manifest_paths,
+                options.isolated,
+                options.custom_config.as_deref(),
+                profile,
+            )?;
+        }
+        let layers = config::resolve_layers(
+            &meta.workspace_root,
+            None,
+            options.isolated,
+            options.custom_config.as_deref(),
+            options.profile.as_deref(),
+            &options.config,
+        )?;
+        let config = config::merge_layers(&layers);
+        let git_root = git::top_level(&meta.workspace_root)?;
+
+        Ok(Workspace {
+            meta,
+            config,
+            git_root,
+        })
+    }
+
+    pub fn meta(&self) -> &cargo_metadata::Metadata {
+        &self.meta
+    }
+
+    /// The workspace-level configuration, e.g. for `consolidate-commits`
+    pub fn config(&self) -> &config::Config {
+        &self.config
+    }
+
+    /// Plan the release of the crates selected by `options`, in dependency order
+    ///
+    /// Crates with `disable-release` are left out.
+    pub fn plan(&self, options: &ReleaseOptions) -> Result<Vec<PackageRelease<'_>>, FatalError> {
+        let ws_meta = &self.meta;
+        let follow_dev = self.config.dev_dependents() == config::DevDependents::Include;
+        let pkg_ids = sort_workspace(ws_meta, follow_dev);
+
+        let mut selection = clap_cargo::Workspace::default();
+        selection.package = options.packages.clone();
+        selection.workspace = options.workspace;
+        selection.exclude = options.exclude.clone();
+        let (selected_pkgs, excluded_pkgs) = selection.partition_packages(ws_meta);
+        if selected_pkgs.is_empty() {
+            log::info!("No packages selected.");
+            return Ok(Vec::new());
+        }
+        let mut all_pkgs = selected_pkgs.clone();
+        all_pkgs.extend(excluded_pkgs);
+        let all_pkgs = all_pkgs;
+
+        let pkg_releases: Result<HashMap<_, _>, _> = selected_pkgs
+            .iter()
+            .filter_map(|p| {
+                PackageRelease::load(options, &self.git_root, ws_meta, &all_pkgs, p).transpose()
+            })
+            .map(|p| p.map(|p| (&p.meta.id, p)))
+            .collect();
+        let mut pkg_releases = pkg_releases?;
+        let pkg_releases: Vec<_> = pkg_ids
+            .into_iter()
+            .filter_map(|id| pkg_releases.remove(id))
+            .collect();
+        check_inherited_versions(ws_meta, &pkg_releases)?;
+        Ok(pkg_releases)
+    }
+
+    /// Execute a release plan, reporting progress to `observer`
+    ///
+    /// Returns the process exit code, `0` on success.
+    pub fn release(
+        &self,
+        options: &ReleaseOptions,
+        pkgs: &[PackageRelease<'_>],
+        observer: &mut dyn Observer,
+    ) -> Result<i32, FatalError> {
+        release_packages(options, &self.meta, &self.config, pkgs, observer)
+    }
+}
+
+/// Release the crates selected by `options`, confirming on the terminal
+pub fn release_workspace(options: &ReleaseOptions) -> Result<i32, error::FatalError> {
+    let ws = Workspace::load(options)?;
+    let pkgs = ws.plan(options)?;
+    if pkgs.is_empty() {
+        return Ok(0);
+    }
+    ws.release(options, &pkgs, &mut ShellObserver)
+}
+
+/// Steps of a release, in the order they are executed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step {
+    /// Run the `verify-commands`
+    Verify,
+    /// Update versions and commit the release
+    Commit,
+    /// `cargo publish`
+    Publish,
+    /// Create the git tags
+    Tag,
+    /// Start the next development iteration
+    Bump,
+    /// `git push`
+    Push,
+    /// Update the `downstream` repositories
+    Downstream,
+}
+
+/// Follows the progress of a release
+pub trait Observer {
+    /// Whether to go ahead with the release described by `prompt`
+    fn confirm(&mut self, prompt: &str) -> bool;
+
+    /// `step` is starting
+    fn step(&mut self, _step: Step) {}
+
+    /// `step` is being applied to `pkg`
+    fn package(&mut self, _step: Step, _pkg: &PackageRelease<'_>) {}
+}
+
+/// Asks for confirmation on the terminal
+pub struct ShellObserver;
+
+impl Observer for ShellObserver {
+    fn confirm(&mut self, prompt: &str) -> bool {
+        shell::confirm(prompt)
+    }
+}
+
+/// Order the workspace members so dependencies come first, `follow_dev` including dev-dependencies
+fn sort_workspace(
+    ws_meta: &cargo_metadata::Metadata,
+    follow_dev: bool,
+) -> Vec<&cargo_metadata::PackageId> {
+    let members: HashSet<_> = ws_meta.workspace_members.iter().collect();
+    let dep_tree: HashMap<_, _> = ws_meta
+        .resolve
+        .as_ref()
+        .expect("cargo-metadata resolved deps")
+        .nodes
+        .iter()
+        .filter_map(|n| {
+            if members.contains(&n.id) {
+                let deps: Vec<_> = n
+                    .deps
+                    .iter()
+                    .filter(|d| {
+                        follow_dev
+                            || d.dep_kinds.is_empty()
+                            || d.dep_kinds
+                                .iter()
+                                .any(|k| k.kind != cargo_metadata::DependencyKind::Development)
+                    })
+                    .map(|d| &d.pkg)
+                    .collect();
+                Some((&n.id, deps))
+            } else {
+                None
+            }
+        })
+        .collect();
+
+    let mut sorted = Vec::new();
+    let mut processed = HashSet::new();
+    for pkg_id in ws_meta.workspace_members.iter() {
+        sort_workspace_inner(ws_meta, pkg_id, &dep_tree, &mut processed, &mut sorted);
+    }
+
+    sorted
+}
+
+fn sort_workspace_inner<'m>(
+    ws_meta: &'m cargo_metadata::Metadata,
+    pkg_id: &'m cargo_metadata::PackageId,
+    dep_tree: &HashMap<&'m cargo_metadata::PackageId, Vec<&'m cargo_metadata::PackageId>>,
+    processed: &mut HashSet<&'m cargo_metadata::PackageId>,
+    sorted: &mut Vec<&'m cargo_metadata::PackageId>,
+) {
+    if !processed.insert(pkg_id) {
+        return;
+    }
+
+    for dep_id in dep_tree[pkg_id]
+        .iter()
+        .copied()
+        .filter(|dep_id| dep_tree.contains_key(dep_id))
+    {
+        sort_workspace_inner(ws_meta, dep_id, dep_tree, processed, sorted);
+    }
+
+    sorted.push(pkg_id);
+}
+
+fn release_packages(
+    options: &ReleaseOptions,
+    ws_meta: &cargo_metadata::Metadata,
+    ws_config: &config::Config,
+    pkgs: &[PackageRelease<'_>],
+    observer: &mut dyn Observer,
+) -> Result<i32, error::FatalError> {
+    let dry_run = options.dry_run;
+
+    // STEP 0: Help the user make the right decisions.
+    git::git_version()?;
+    let mut dirty = false;
+    if ws_config.consolidate_commits() {
+        if git::is_dirty(&ws_meta.workspace_root)? {
+            log::warn!("Uncommitted changes detected, please commit before release.");
+            dirty = true;
+        }
+    } else {
+        for pkg in pkgs {
+            let cwd = pkg.package_path;
+            if git::is_dirty(cwd)? {
+                let crate_name = pkg.meta.name.as_str();
+                log::warn!(
+                    "Uncommitted changes detected for {}, please commit before release.",
+                    crate_name
+                );
+                dirty = true;
+            }
+        }
+    }
+    if dirty {
+        if !options.dry_run {
+            return Ok(101);
+        }
+    }
+
+    let lock_path = ws_meta.workspace_root.join("Cargo.lock");
+    for pkg in pkgs {
+        if let Some(version) = pkg.version.as_ref() {
+            let crate_name = pkg.meta.name.as_str();
+            let prev_tag_name = &pkg.prev_tag;
+            if let Some(mut changed) = pkg.changed_files()? {
+                if let Some(lock_index) = changed.iter().enumerate().find_map(|(idx, path)| {
+                    if path == &lock_path {
+                        Some(idx)
+                    } else {
+                        None
+                    }
+                }) {
+                    log::debug!("Lock file changed since {} but ignored since it could be as simple as a pre-release version bump.", prev_tag_name);
+                    let _ = changed.swap_remove(lock_index);
+                }
+                if changed.is_empty() {
+                    log::warn!(
+                        "Updating {} to {} despite no changes made since tag {}",
+                        crate_name,
+                        version.version_string,
+                        prev_tag_name
+                    );
+                } else {
+                    log::debug!(
+                        "Files changed in {} since {}: {:#?}",
+                        crate_name,
+                        prev_tag_name,
+                        changed
+                    );
+                }
+            } else {
+                log::debug!(
+                    "Cannot detect changes for {} because tag {} is missing. Try setting `--prev-tag-name <TAG>`.",
+                    crate_name,
+                    prev_tag_name
+                );
+            }
+        }
+    }
+
+    let mut api_broken = false;
+    for pkg in pkgs {
+        if pkg.config.check_api() && !check_api(pkg, ws_meta)? {
+            api_broken = true;
+        }
+    }
+    if api_broken {
+        return Ok(108);
+    }
+
+    for pkg in pkgs {
+        if tag_message_uses(pkg, CRATE_CHECKSUM) && pkg.config.disable_publish() {
+            return Err(FatalError::TagMessageValueUnavailable(
+                CRATE_CHECKSUM,
+                pkg.meta.name.clone(),
+                "publishing is disabled".to_owned(),
+            ));
+        }
+    }
+
+    let push_targets = ws_config.push_targets();
+    let branch = git::current_branch(&ws_meta.workspace_root)?;
+    if branch == "HEAD" {
+        log::warn!("Releasing from a detached HEAD");
+    }
+    for target in &push_targets {
+        let remote_branch = target.remote_branch(&branch);
+        git::fetch(&ws_meta.workspace_root, &target.remote, remote_branch)?;
+        if git::is_behind_remote(
+            &ws_meta.workspace_root,
+            &target.remote,
+            remote_branch,
+            &branch,
+        )? {
+            log::warn!("{} is behind {}/{}", branch, target.remote, remote_branch);
+        }
+    }
+
+    // STEP 1: Release Confirmation
+    if !dry_run && !options.no_confirm {
+        let prompt = if pkgs.len() == 1 {
+            let pkg = &pkgs[0];
+            let crate_name = pkg.meta.name.as_str();
+            let base = pkg.version.as_ref().unwrap_or(&pkg.prev_version);
+            format!("Release {} {}?", crate_name, base.version_string)
+        } else {
+            let mut buffer: Vec<u8> = vec![];
+            writeln!(&mut buffer, "Release").unwrap();
+            for pkg in pkgs {
+                let crate_name = pkg.meta.name.as_str();
+                let base = pkg.version.as_ref().unwrap_or(&pkg.prev_version);
+                writeln!(&mut buffer, "  {} {}", crate_name, base.version_string).unwrap();
+            }
+            write!(&mut buffer, "?").unwrap();
+            String::from_utf8(buffer).expect("Only valid UTF-8 has been written")
+        };
+
+        let confirmed = observer.confirm(&prompt);
+        if !confirmed {
+            return Ok(0);
+        }
+    }
+
+    // Verify the crates before changing anything
+    observer.step(Step::Verify);
+    let mut verify_failures = Vec::new();
+    // Commands inherited from the workspace config cover every crate, so they only run once per
+    // feature set
+    let ws_verify_commands = ws_config.verify_commands();
+    let mut ws_feature_args: Vec<Vec<String>> = Vec::new();
+    for pkg in pkgs {
+        if pkg.version.is_none() || pkg.config.disable_verify() {
+            continue;
+        }
+        let feature_args = feature_args(&pkg.features);
+        let verify_commands = pkg.config.verify_commands();
+        if !verify_commands.is_empty() && verify_commands == ws_verify_commands {
+            if !ws_feature_args.contains(&feature_args) {
+                ws_feature_args.push(feature_args);
+            }
+            continue;
+        }
+        observer.package(Step::Verify, pkg);
+        let cwd = pkg.package_path;
+        let crate_name = pkg.meta.name.as_str();
+        for command in verify_commands {
+            // Cargo commands are run with the same features as `cargo publish`
+            let command = with_features(command.args(), &feature_args);
+            let command_line = command.join(" ");
+            log::info!("Verifying {} with `{}`", crate_name, command_line);
+            if !cmd::call_on_path(command, cwd, dry_run)? {
+                verify_failures.push((crate_name, command_line));
+            }
+        }
+    }
+    for feature_args in &ws_feature_args {
+        for command in ws_verify_commands {
+            let command = with_features(command.args(), feature_args);
+            let command_line = command.join(" ");
+            log::info!("Verifying workspace with `{}`", command_line);
+            if !cmd::call_on_path(command, &ws_meta.workspace_root, dry_run)? {
+                verify_failures.push(("workspace", command_line));
+            }
+        }
+    }
+    if !verify_failures.is_empty() {
+        log::error!("Verification failed, aborting release:");
+        for (crate_name, command_line) in verify_failures {
+            log::error!("  {}: `{}`", crate_name, command_line);
+        }
+        return Ok(109);
+    }
+
+    // STEP 2: update current version, save and commit
+    let ws_manifest_path = ws_meta.workspace_root.join("Cargo.toml");
+    let bumped_crates = bumped_crates(ws_meta, pkgs)?;
+    observer.step(Step::Commit);
+    let mut shared_commit = false;
+    for pkg in pkgs {
+        let dry_run = options.dry_run;
+        let cwd = pkg.package_path;
+        let crate_name = pkg.meta.name.as_str();
+
+        if let Some(version) = pkg.version.as_ref() {
+            observer.package(Step::Commit, pkg);
+            let new_version_string = version.version_string.as_str();
+            log::info!("Update {} to version {}", crate_name, new_version_string);
+            let manifests = read_manifests(pkg, &ws_manifest_path)?;
+            if !dry_run {
+                cargo::set_package_version(
+                    pkg.manifest_path,
+                    &ws_manifest_path,
+                    new_version_string,
+                )?;
+            }
+            update_dependent_versions(pkg, version, &ws_manifest_path, dry_run)?;
+            for dev_dependent in &pkg.dev_dependents {
+                log::info!(
+                    "Removing the version of {}'s dev-dependency on {}",
+                    dev_dependent.name,
+                    crate_name
+                );
+                if !dry_run {
+                    cargo::strip_dev_dependency_version(&dev_dependent.manifest_path, crate_name)?;
+                }
+            }
+            if dry_run {
+                log::debug!("Updating lock file");
+            } else if let Err(err) = cargo::update_lock(
+                pkg.manifest_path,
+                &lock_path,
+                crate_name,
+                &pkg.prev_version.version_string,
+            ) {
+                restore_manifests(&manifests)?;
+                return Err(err);
+            }
+
+            if !pkg.config.pre_release_replacements().is_empty() {
+                // try replacing text in configured files
+                let template = Template {
+                    prev_version: Some(&pkg.prev_version.version_string),
+                    version: Some(new_version_string),
+                    crate_name: Some(crate_name),
+                    date: Some(NOW.as_str()),
+                    tag_name: pkg.tag.as_deref(),
+                    ..Default::default()
+                };
+                let prerelease = !version.version.pre.is_empty();
+                do_file_replacements(
+                    pkg.config.pre_release_replacements(),
+                    &template,
+                    cwd,
+                    prerelease,
+                    dry_run,
+                )?;
+            }
+
+            // pre-release hook
+            if let Some(pre_rel_hook) = pkg.config.pre_release_hook() {
+                let pre_rel_hook = pre_rel_hook.args();
+                log::debug!("Calling pre-release hook: {:?}", pre_rel_hook);
+                let envs = maplit::btreemap! {
+                    OsStr::new("PREV_VERSION") => pkg.prev_version.version_string.as_ref(),
+                    OsStr::new("NEW_VERSION") => new_version_string.as_ref(),
+                    OsStr::new("DRY_RUN") => OsStr::new(if dry_run { "true" } else { "false" }),
+                    OsStr::new("CRATE_NAME") => OsStr::new(crate_name),
+                    OsStr::new("WORKSPACE_ROOT") => ws_meta.workspace_root.as_os_str(),
+                    OsStr::new("CRATE_ROOT") => pkg.manifest_path.parent().unwrap_or_else(|| Path::new(".")).as_os_str(),
+                };
+                // we use dry_run environmental variable to run the script
+                // so here we set dry_run=false and always execute the command.
+                if !cmd::call_with_env(pre_rel_hook, envs, cwd, false)? {
+                    log::warn!(
+                        "Release of {} aborted by non-zero return of prerelease hook.",
+                        crate_name
+                    );
+                    return Ok(107);
+                }
+            }
+
+            if ws_config.consolidate_commits() {
+                shared_commit = true;
+            } else {
+                let template = Template {
+                    prev_version: Some(&pkg.prev_version.version_string),
+                    version: Some(new_version_string),
+                    crate_name: Some(crate_name),
+                    date: Some(NOW.as_str()),
+                    ..Default::default()
+                };
+                let commit_msg = template.render(pkg.config.pre_release_commit_message());
+                let sign = pkg.config.sign_commit();
+                if !dry_run {
+                    verify_lock(&ws_meta.workspace_root, &lock_path, &bumped_crates)?;
+                }
+                if !git::commit_all(cwd, &commit_msg, sign, dry_run)? {
+                    // commit failed, abort release
+                    return Ok(102);
+                }
+            }
+        }
+    }
+    if shared_commit {
+        if !dry_run {
+            verify_lock(&ws_meta.workspace_root, &lock_path, &bumped_crates)?;
+        }
+        let shared_commit_msg = {
+            let template = Template {
+                date: Some(NOW.as_str()),
+                ..Default::default()
+            };
+            template.render(ws_config.pre_release_commit_message())
+        };
+        if !git::commit_all(
+            &ws_meta.workspace_root,
+            &shared_commit_msg,
+            ws_config.sign_commit(),
+            dry_run,
+        )? {
+            // commit failed, abort release
+            return Ok(102);
+        }
+    }
+
+    // Before publishing, as the section is only named after the version by the release commit
+    let mut release_notes = HashMap::new();
+    for pkg in pkgs {
+        if tag_message_uses(pkg, CHANGELOG) {
+            let base = pkg.version.as_ref().unwrap_or(&pkg.prev_version);
+            match changelog_notes(pkg, &base.version_string)? {
+                Some(notes) => {
+                    release_notes.insert(pkg.meta.name.as_str(), notes);
+                }
+                None if dry_run => {
+                    log::warn!(
+                        "No section for {} in the changelog yet",
+                        base.version_string
+                    );
+                }
+                None => {
+                    return Err(FatalError::TagMessageValueUnavailable(
+                        CHANGELOG,
+                        pkg.meta.name.clone(),
+                        format!("no CHANGELOG.md section for {}", base.version_string),
+                    ));
+                }
+            }
+        }
+    }
+
+    // STEP 3: cargo publish
+    observer.step(Step::Publish);
+    let mut checksums = HashMap::new();
+    for pkg in pkgs {
+        if !pkg.config.disable_publish() {
+            observer.package(Step::Publish, pkg);
+            let crate_name = pkg.meta.name.as_str();
+            let base = pkg.version.as_ref().unwrap_or(&pkg.prev_version);
+
+            log::info!("Running cargo publish on {}", crate_name);
+            // feature list to release
+            let features = &pkg.features;
+            if !cargo::publish(
+                dry_run,
+                pkg.manifest_path,
+                features,
+                pkg.config.registry(),
+                options.token.as_deref(),
+            )? {
+                return Ok(103);
+            }
+            let timeout = std::time::Duration::from_secs(300);
+
+            if pkg.config.registry().is_none() {
+                cargo::wait_for_publish(crate_name, &base.version_string, timeout, dry_run)?;
+                // HACK: Even once the index is updated, there seems to be another step before the publish is fully ready.
+                // We don't have a way yet to check for that, so waiting for now in hopes everything is ready
+                if !dry_run {
+                    let publish_grace_sleep = std::env::var("PUBLISH_GRACE_SLEEP")
+                        .unwrap_or_else(|_| String::from("5"))
+                        .parse()
+                        .unwrap_or(5);
+                    log::info!(
+                        "Waiting an additional {} seconds for crates.io to update its indices...",
+                        publish_grace_sleep
+                    );
+                    std::thread::sleep(std::time::Duration::from_secs(publish_grace_sleep));
+                }
+            } else {
+                log::debug!("Not waiting for publish because the registry is not crates.io and doesn't get updated automatically");
+            }
+
+            if tag_message_uses(pkg, CRATE_CHECKSUM) && !dry_run {
+                let checksum = published_checksum(ws_meta, pkg, &base.version_string)?;
+                checksums.insert(crate_name, checksum);
+            }
+        }
+    }
+
+    // STEP 5: Tag
+    observer.step(Step::Tag);
+    for pkg in pkgs {
+        if let Some(tag_name) = pkg.tag.as_ref() {
+            observer.package(Step::Tag, pkg);
+            let sign = pkg.config.sign_commit() || pkg.config.sign_tag();
+
+            // FIXME: remove when the meaning of sign_commit is changed
+            if !pkg.config.sign_tag() && pkg.config.sign_commit() {
+                log::warn!("In next minor release, `sign-commit` will only be used to control git commit signing. Use option `sign-tag` for tag signing.");
+            }
+
+            let cwd = pkg.package_path;
+            let crate_name = pkg.meta.name.as_str();
+
+            let base = pkg.version.as_ref().unwrap_or(&pkg.prev_version);
+            let tag_message = match pkg.config.tag_kind() {
+                config::TagKind::Annotated => {
+                    // Only missing on dry runs
+                    let changelog = release_notes.get(crate_name).map_or("", String::as_str);
+                    let crate_checksum = checksums.get(crate_name).map_or("", String::as_str);
+                    let template = Template {
+                        prev_version: Some(&pkg.prev_version.version_string),
+                        version: Some(&base.version_string),
+                        crate_name: Some(crate_name),
+                        tag_name: Some(tag_name),
+                        date: Some(NOW.as_str()),
+                        changelog: Some(changelog),
+                        crate_checksum: Some(crate_checksum),
+                        ..Default::default()
+                    };
+                    Some(template.render(pkg.config.tag_message()))
+                }
+                config::TagKind::Lightweight => {
+                    if pkg.config.sign_tag() {
+                        log::warn!("Lightweight tag {} can't be signed", tag_name);
+                    }
+                    None
+                }
+            };
+
+            log::debug!("Creating git tag {}", tag_name);
+            if !git::tag(cwd, tag_name, tag_message.as_deref(), sign, dry_run)? {
+                // tag failed, abort release
+                return Ok(104);
+            }
+        }
+    }
+
+    // STEP 6: bump version
+    observer.step(Step::Bump);
+    let mut shared_commit = false;
+    for pkg in pkgs {
+        if let Some(version) = pkg.post_version.as_ref() {
+            observer.package(Step::Bump, pkg);
+            let cwd = pkg.package_path;
+            let crate_name = pkg.meta.name.as_str();
+
+            let updated_version_string = version.version_string.as_ref();
+            log::info!(
+                "Starting {}'s next development iteration {}",
+                crate_name,
+                updated_version_string,
+            );
+            let base = pkg.version.as_ref().unwrap_or(&pkg.prev_version);
+            let manifests = read_manifests(pkg, &ws_manifest_path)?;
+            update_dependent_versions(pkg, version, &ws_manifest_path, dry_run)?;
+            if !dry_run {
+                cargo::set_package_version(
+                    pkg.manifest_path,
+                    &ws_manifest_path,
+                    updated_version_string,
+                )?;
+                if let Err(err) = cargo::update_lock(
+                    pkg.manifest_path,
+                    &lock_path,
+                    crate_name,
+                    &base.version_string,
+                ) {
+                    restore_manifests(&manifests)?;
+                    return Err(err);
+                }
+            }
+            let template = Template {
+                prev_version: Some(&pkg.prev_version.version_string),
+                version: Some(&base.version_string),
+                crate_name: Some(crate_name),
+                date: Some(NOW.as_str()),
+                tag_name: pkg.tag.as_deref(),
+                next_version: Some(updated_version_string),
+                ..Default::default()
+            };
+            if !pkg.config.post_release_replacements().is_empty() {
+                // try replacing text in configured files
+                do_file_replacements(
+                    pkg.config.post_release_replacements(),
+                    &template,
+                    cwd,
+                    false, // post-release replacements should always be applied
+                    dry_run,
+                )?;
+            }
+            let commit_msg = template.render(pkg.config.post_release_commit_message());
+
+            if ws_config.consolidate_commits() {
+                shared_commit = true;
+            } else {
+                let sign = pkg.config.sign_commit();
+                if !git::commit_all(cwd, &commit_msg, sign, dry_run)? {
+                    return Ok(105);
+                }
+            }
+        }
+    }
+    if shared_commit {
+        let shared_commit_msg = {
+            let template = Template {
+                date: Some(NOW.as_str()),
+                ..Default::default()
+            };
+            template.render(ws_config.post_release_commit_message())
+        };
+        if !git::commit_all(
+            &ws_meta.workspace_root,
+            &shared_commit_msg,
+            ws_config.sign_commit(),
+            dry_run,
+        )? {
+            // commit failed, abort release
+            return Ok(102);
+        }
+    }
+
+    // STEP 7: git push
+    let pushed_pkgs: Vec<_> = pkgs
+        .iter()
+        .filter(|pkg| !pkg.config.disable_push())
+        .collect();
+    if !ws_config.disable_push() && !pushed_pkgs.is_empty() {
+        observer.step(Step::Push);
+        let mut tag_refs = Vec::new();
+        let mut push_options: Vec<String> = Vec::new();
+        for pkg in &pushed_pkgs {
+            observer.package(Step::Push, pkg);
+            if let Some(tag_name) = pkg.tag.as_ref() {
+                tag_refs.push(format!("refs/tags/{}", tag_name));
+            }
+            for option in pkg.config.push_options() {
+                if !push_options.contains(option) {
+                    push_options.push(option.clone());
+                }
+            }
+        }
+        let pushes: Vec<_> = push_targets
+            .iter()
+            .map(|target| {
+                let mut refs = vec![target.refspec().to_owned()];
+                if !target.disable_tags() {
+                    refs.extend(tag_refs.iter().cloned());
+                }
+                let push_options = target.push_options.as_deref().unwrap_or(&push_options);
+                (target, refs, push_options)
+            })
+            .collect();
+        // Each push is only atomic for its own remote, so make sure all of them would go through
+        // before updating any
+        if 1 < pushes.len() && !dry_run {
+            for (target, refs, push_options) in &pushes {
+                log::debug!(
+                    "Checking that {} accepts {}",
+                    target.remote,
+                    refs.join(", ")
+                );
+                if !git::can_push(&ws_meta.workspace_root, &target.remote, refs, push_options)? {
+                    return Ok(106);
+                }
+            }
+        }
+        for (target, refs, push_options) in &pushes {
+            log::info!("Pushing {} to {}", refs.join(", "), target.remote);
+            if !git::push(
+                &ws_meta.workspace_root,
+                &target.remote,
+                refs,
+                push_options,
+                dry_run,
+            )? {
+                return Ok(106);
+            }
+        }
+    }
+
+    // STEP 8: update downstream repositories
+    if !ws_config.downstream().is_empty() {
+        observer.step(Step::Downstream);
+        // The release is already out, so a downstream that can't be updated doesn't fail it
+        for downstream in ws_config.downstream() {
+            match update_downstream(ws_meta, downstream, pkgs, ws_config.sign_commit(), dry_run) {
+                Ok(true) => {}
+                Ok(false) => log::warn!("Failed to update {}", downstream.path.display()),
+                Err(e) => log::warn!("Failed to update {}: {}", downstream.path.display(), e),
+            }
+        }
+    }
+
+    Ok(0)
+}
+
+const CHANGELOG: &str = "{{changelog}}";
+const CRATE_CHECKSUM: &str = "{{crate_checksum}}";
+
+/// The manifests a version change of `pkg` may edit, with their current content
+fn read_manifests(
+    pkg: &PackageRelease<'_>,
+    ws_manifest_path: &Path,
+) -> Result<Vec<(PathBuf, String)>, FatalError> {
+    let mut paths = vec![pkg.manifest_path, ws_manifest_path];
+    paths.extend(
+        pkg.dependents
+            .iter()
+            .map(|dep| dep.pkg.manifest_path.as_path()),
+    );
+    paths.extend(
+        pkg.dev_dependents
+            .iter()
+            .map(|dep| dep.manifest_path.as_path()),
+    );
+
+    let mut manifests: Vec<(PathBuf, String)> = Vec::new();
+    for path in paths {
+        if manifests.iter().all(|(p, _)| p != path) {
+            manifests.push((path.to_owned(), std::fs::read_to_string(path)?));
+        }
+    }
+    Ok(manifests)
+}
+
+/// Undo the edits to `manifests` when the release can't go on
+fn restore_manifests(manifests: &[(PathBuf, String)]) -> Result<(), FatalError> {
+    for (path, content) in manifests {
+        log::debug!("Restoring {}", path.display());
+        std::fs::write(path, content)?;
+    }
+    Ok(())
+}
+
+/// The `cargo` flags selecting `features`
+fn feature_args(features: &Features) -> Vec<String> {
+    match features {
+        Features::None => vec![],
+        Features::Selective(vec) => vec!["--features".to_owned(), vec.join(" ")],
+        Features::All => vec!["--all-features".to_owned()],
+    }
+}
+
+/// Insert `feature_args` after the subcommand of a `cargo [+toolchain] <subcommand>` command
+fn with_features<'a>(mut command: Vec<&'a str>, feature_args: &'a [String]) -> Vec<&'a str> {
+    if command.first() != Some(&"cargo") {
+        return command;
+    }
+    let subcommand = command
+        .iter()
+        .skip(1)
+        .position(|arg| !arg.starts_with('+'))
+        .map(|i| i + 1);
+    if let Some(subcommand) = subcommand {
+        let subcommand_args = command.split_off(subcommand + 1);
+        command.extend(feature_args.iter().map(|s| s.as_str()));
+        command.extend(subcommand_args);
+    }
+    command
+}
+
+/// Whether the annotation of `pkg`'s tag needs the value of `placeholder`
+fn tag_message_uses(pkg: &PackageRelease<'_>, placeholder: &str) -> bool {
+    pkg.tag.is_some()
+        && pkg.config.tag_kind() == config::TagKind::Annotated
+        && pkg.config.tag_message().contains(placeholder)
+}
+
+/// The section of the crate's CHANGELOG.md for `version`
+fn changelog_notes(pkg: &PackageRelease<'_>, version: &str) -> Result<Option<String>, FatalError> {
+    let path = pkg.package_path.join("CHANGELOG.md");
+    if !path.exists() {
+        return Ok(None);
+    }
+    let changelog = std::fs::read_to_string(&path)?;
+    Ok(changelog_section(&changelog, version))
+}
+
+/// The checksum of the `.crate` file just published for `version`, from the crates.io index or,
+/// for other registries, from the file `cargo publish` uploaded
+fn published_checksum(
+    ws_meta: &cargo_metadata::Metadata,
+    pkg: &PackageRelease<'_>,
+    version: &str,
+) -> Result<String, FatalError> {
+    let name = pkg.meta.name.as_str();
+    let checksum = if pkg.config.registry().is_none() {
+        let index = crates_index::Index::new_cargo_default();
+        cargo::indexed_checksum(&index, name, version)
+    } else {
+        cargo::crate_checksum(&ws_meta.target_directory, name, version)?
+    };
+    checksum.ok_or_else(|| {
+        FatalError::TagMessageValueUnavailable(
+            CRATE_CHECKSUM,
+            name.to_owned(),
+            format!("no published .crate found for {}", version),
+        )
+    })
+}
+
+/// Releasing a crate inheriting the `[workspace.package]` version changes it for every crate
+/// inheriting it, so they have to be released together
+fn check_inherited_versions(
+    ws_meta: &cargo_metadata::Metadata,
+    pkgs: &[PackageRelease<'_>],
+) -> Result<(), FatalError> {
+    let mut inherited = false;
+    for pkg in pkgs.iter().filter(|pkg| pkg.version.is_some()) {
+        inherited |= cargo::is_version_inherited(pkg.manifest_path)?;
+    }
+    if !inherited {
+        return Ok(());
+    }
+
+    let mut unreleased = Vec::new();
+    for member in ws_meta
+        .packages
+        .iter()
+        .filter(|p| ws_meta.workspace_members.contains(&p.id))
+    {
+        let released = pkgs
+            .iter()
+            .any(|pkg| pkg.meta.id == member.id && pkg.version.is_some());
+        if !released && cargo::is_version_inherited(&member.manifest_path)? {
+            unreleased.push(member.name.clone());
+        }
+    }
+    if unreleased.is_empty() {
+        Ok(())
+    } else {
+        Err(FatalError::InheritedVersionNotReleased(unreleased))
+    }
+}
+
+/// Workspace crates whose version the release commit changes, including those inheriting the
+/// version of a released crate
+fn bumped_crates<'m>(
+    ws_meta: &'m cargo_metadata::Metadata,
+    pkgs: &[PackageRelease<'m>],
+) -> Result<HashSet<&'m str>, FatalError> {
+    let mut bumped = HashSet::new();
+    let mut inherited = false;
+    for pkg in pkgs.iter().filter(|pkg| pkg.version.is_some()) {
+        bumped.insert(pkg.meta.name.as_str());
+        inherited |= cargo::is_version_inherited(pkg.manifest_path)?;
+    }
+    if inherited {
+        for member in ws_meta
+            .packages
+            .iter()
+            .filter(|p| ws_meta.workspace_members.contains(&p.id))
+        {
+            if cargo::is_version_inherited(&member.manifest_path)? {
+                bumped.insert(member.name.as_str());
+            }
+        }
+    }
+    Ok(bumped)
+}
+
+/// Make sure the changes to the lock file since HEAD are limited to the versions of
+/// `bumped_crates`
+fn verify_lock(
+    dir: &Path,
+    lock_path: &Path,
+    bumped_crates: &HashSet<&str>,
+) -> Result<(), FatalError> {
+    let relative_lock_path = lock_path.strip_prefix(dir).unwrap_or(lock_path);
+    let committed = match git::file_at(dir, "HEAD", relative_lock_path)? {
+        Some(committed) => committed,
+        // Not tracked
+        None => return Ok(()),
+    };
+    let current = std::fs::read_to_string(lock_path)?;
+    let changed =
+        cargo::changed_lock_entries(&committed, &current, &|name| bumped_crates.contains(name))?;
+    if changed.is_empty() {
+        Ok(())
+    } else {
+        Err(FatalError::UnrelatedLockChanges(changed))
+    }
+}
+
+/// Update `downstream`'s requirements on the released crates and its lock file, committing the
+/// result on a new branch.
+///
+/// Returns `false` when the checkout can't be updated, after switching it back to its original
+/// branch.
+fn update_downstream(
+    ws_meta: &cargo_metadata::Metadata,
+    downstream: &config::Downstream,
+    pkgs: &[PackageRelease<'_>],
+    sign: bool,
+    dry_run: bool,
+) -> Result<bool, FatalError> {
+    let root = ws_meta.workspace_root.join(&downstream.path);
+    let meta = cargo_metadata::MetadataCommand::new()
+        .manifest_path(root.join("Cargo.toml"))
+        .no_deps()
+        .exec()
+        .map_err(FatalError::from)?;
+    let manifest_path = meta.workspace_root.join("Cargo.toml");
+
+    let released: Vec<_> = pkgs
+        .iter()
+        .filter_map(|pkg| {
+            let version = pkg.version.as_ref()?;
+            let dependents = collect_dependents(find_dependents(&meta, pkg.meta));
+            if dependents.is_empty() {
+                None
+            } else {
+                Some((pkg, version, dependents))
+            }
+        })
+        .collect();
+    if released.is_empty() {
+        log::debug!("{} doesn't depend on the released crates", root.display());
+        return Ok(true);
+    }
+
+    if git::is_dirty(&root)? {
+        log::warn!("Uncommitted changes in {}, not updating it", root.display());
+        return Ok(false);
+    }
+    let single = match released.as_slice() {
+        [(pkg, version, _)] => Some((pkg.meta.name.as_str(), version.version_string.as_str())),
+        _ => None,
+    };
+    let template = Template {
+        crate_name: single.map(|(name, _)| name),
+        version: single.map(|(_, version)| version),
+        date: Some(NOW.as_str()),
+        ..Default::default()
+    };
+    let branch = template.render(downstream.branch());
+    log::info!("Updating {} on branch {}", root.display(), branch);
+    let original_branch = git::current_branch(&root)?;
+    if !git::create_branch(&root, &branch, dry_run)? {
+        return Ok(false);
+    }
+
+    let updated = commit_downstream(&root, &manifest_path, &released, sign, dry_run);
+    if !matches!(updated, Ok(true)) {
+        log::info!("Restoring {} to branch {}", root.display(), original_branch);
+        git::force_checkout(&root, &original_branch, dry_run)?;
+        git::delete_branch(&root, &branch, dry_run)?;
+    }
+    updated
+}
+
+fn commit_downstream(
+    root: &Path,
+    manifest_path: &Path,
+    released: &[(&PackageRelease<'_>, &Version, Vec<Dependency<'_>>)],
+    sign: bool,
+    dry_run: bool,
+) -> Result<bool, FatalError> {
+    for (pkg, version, dependents) in released {
+        if !update_requirements(pkg, version, dependents, manifest_path, dry_run)? {
+            return Ok(false);
+        }
+        // An unpublished version can't be resolved from the registry
+        if pkg.config.disable_publish() {
+            log::debug!(
+                "{} wasn't published, not updating the lock file",
+                pkg.meta.name
+            );
+        } else if !cargo::update_package(manifest_path, &pkg.meta.name, dry_run)? {
+            return Ok(false);
+        }
+    }
+
+    let updates: Vec<_> = released
+        .iter()
+        .map(|(pkg, version, _)| format!("{} to {}", pkg.meta.name, version.version_string))
+        .collect();
+    let commit_msg = format!("Update {}", updates.join(", "));
+    git::commit_all(root, &commit_msg, sign, dry_run)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[allow(unused_imports)] // Not being detected
+    use assert_fs::prelude::*;
+    use predicates::prelude::*;
+
+    fn git(dir: &Path, args: &[&str]) {
+        let status = std::process::Command::new("git")
+            .args(args)
+            .current_dir(dir)
+            .status()
+            .unwrap();
+        assert!(status.success(), "git {:?} failed", args);
+    }
+
+    fn init_repo(dir: &Path) {
+        git(dir, &["init", "--quiet"]);
+        // For the commits of the code under test too
+        git(dir, &["config", "user.name", "Test"]);
+        git(dir, &["config", "user.email", "test@example.com"]);
+        git(dir, &["add", "--all"]);
+        git(dir, &["commit", "--quiet", "--message", "Initial"]);
+    }
+
+    mod update_downstream {
+        use super::*;
+
+        #[test]
+        fn string_and_table_requirements() {
+            let temp = assert_fs::TempDir::new().unwrap();
+            let upstream = temp.child("upstream");
+            upstream
+                .copy_from("tests/fixtures/simple", &["**"])
+                .unwrap();
+            init_repo(upstream.path());
+            let downstream = temp.child("downstream");
+            downstream
+                .copy_from("tests/fixtures/downstream", &["**"])
+                .unwrap();
+            init_repo(downstream.path());
+
+            let options = ReleaseOptions {
+                manifest_path: Some(upstream.path().join("Cargo.toml")),
+                level_or_version: "minor".to_owned(),
+                config: config::Config {
+                    // Keeps `cargo update` from looking up the new version
+                    disable_publish: Some(true),
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            let ws = Workspace::load(&options).unwrap();
+            let pkgs = ws.plan(&options).unwrap();
+            let config = config::Downstream {
+                path: PathBuf::from("../downstream"),
+                branch: Some("update-simple".to_owned()),
+            };
+
+            assert!(update_downstream(ws.meta(), &config, &pkgs, false, false).unwrap());
+
+            downstream.child("Cargo.toml").assert(
+                predicate::str::contains("simple = \"^0.2\"  # string requirement\n")
+                    .and(predicate::str::contains("simple = { version = \"^0.2\"")),
+            );
+            assert_eq!(
+                git::current_branch(downstream.path()).unwrap(),
+                "update-simple"
+            );
+            assert!(!git::is_dirty(downstream.path()).unwrap());
+
+            temp.close().unwrap();
+        }
+    }
+}
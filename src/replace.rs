@@ -16,6 +16,12 @@ pub struct Template<'a> {
     pub prefix: Option<&'a str>,
     pub tag_name: Option<&'a str>,
     pub next_version: Option<&'a str>,
+
+    pub git_sha_short: Option<&'a str>,
+    pub commit_count: Option<&'a str>,
+
+    pub changelog: Option<&'a str>,
+    pub crate_checksum: Option<&'a str>,
 }
 
 impl<'a> Template<'a> {
@@ -43,6 +49,20 @@ impl<'a> Template<'a> {
         if let Some(next_version) = self.next_version {
             s = s.replace("{{next_version}}", next_version);
         }
+
+        if let Some(git_sha_short) = self.git_sha_short {
+            s = s.replace("{{git_sha_short}}", git_sha_short);
+        }
+        if let Some(commit_count) = self.commit_count {
+            s = s.replace("{{commit_count}}", commit_count);
+        }
+
+        if let Some(changelog) = self.changelog {
+            s = s.replace("{{changelog}}", changelog);
+        }
+        if let Some(crate_checksum) = self.crate_checksum {
+            s = s.replace("{{crate_checksum}}", crate_checksum);
+        }
         s
     }
 }
@@ -124,3 +144,65 @@ pub fn do_file_replacements(
     }
     Ok(true)
 }
+
+/// The body of the section of `changelog` whose heading names `version`, up to the next heading
+/// of the same or a higher level
+pub fn changelog_section(changelog: &str, version: &str) -> Option<String> {
+    let mut lines = changelog.lines();
+    let level = lines.find_map(|line| {
+        let level = line.chars().take_while(|c| *c == '#').count();
+        if 0 < level && names_version(line, version) {
+            Some(level)
+        } else {
+            None
+        }
+    })?;
+    let section: Vec<_> = lines
+        .take_while(|line| {
+            let next_level = line.chars().take_while(|c| *c == '#').count();
+            next_level == 0 || level < next_level
+        })
+        .collect();
+    Some(section.join("\n").trim_matches('\n').to_owned())
+}
+
+/// Whether `line` contains `version` on its own, e.g. not as part of `1.0.10` for `1.0.1`
+fn names_version(line: &str, version: &str) -> bool {
+    line.match_indices(version).any(|(i, _)| {
+        let before = line[..i].chars().next_back();
+        let after = line[i + version.len()..].chars().next();
+        !matches!(before, Some(c) if c.is_ascii_digit() || c == '.')
+            && !matches!(after, Some(c) if c.is_ascii_alphanumeric() || ".-+".contains(c))
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn changelog_section_for_version() {
+        let changelog = "\
+# Change Log
+
+## [Unreleased] - ReleaseDate
+
+## [1.0.10] - 2021-08-02
+
+* Later
+
+## [1.0.1] - 2021-08-01
+
+### Fixed
+
+* Something
+
+## [1.0.0] - 2021-07-01
+";
+        assert_eq!(
+            changelog_section(changelog, "1.0.1").as_deref(),
+            Some("### Fixed\n\n* Something")
+        );
+        assert_eq!(changelog_section(changelog, "1.0.2"), None);
+    }
+}
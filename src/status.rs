@@ -0,0 +1,209 @@
+//! `cargo release status`
+
+use std::path::PathBuf;
+
+use crate::args::StatusOpt;
+use crate::error::FatalError;
+use crate::release::{ReleaseOptions, Workspace};
+use crate::{cargo, git};
+
+/// Where a crate stands since its last release
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct CrateStatus {
+    name: String,
+    version: String,
+    tag: Option<String>,
+    commits: Option<String>,
+    changed_files: Option<usize>,
+    published: Option<bool>,
+    tag_pushed: Option<bool>,
+}
+
+/// Print a table of the selected crates, by default all of the workspace
+pub fn run(opt: &StatusOpt) -> Result<i32, FatalError> {
+    let options = ReleaseOptions {
+        manifest_path: opt.manifest.manifest_path.clone(),
+        packages: opt.workspace.package.clone(),
+        workspace: opt.workspace.package.is_empty() || opt.workspace.workspace || opt.workspace.all,
+        exclude: opt.workspace.exclude.clone(),
+        custom_config: opt.custom_config.as_ref().map(PathBuf::from),
+        isolated: opt.isolated,
+        profile: opt.profile.clone(),
+        ..Default::default()
+    };
+
+    let ws = Workspace::load(&options)?;
+    let pkgs = ws.plan(&options)?;
+
+    let index = if opt.offline {
+        None
+    } else {
+        let index = crates_index::Index::new_cargo_default();
+        if index.exists() {
+            if let Err(e) = index.update() {
+                log::debug!("Crate index update failed with {}", e);
+            }
+            Some(index)
+        } else {
+            log::debug!("No local copy of the crates.io index, skipping publish checks");
+            None
+        }
+    };
+    let lock_path = ws.meta().workspace_root.join("Cargo.lock");
+
+    let mut statuses = Vec::new();
+    for pkg in &pkgs {
+        let cwd = pkg.package_path();
+        let name = pkg.meta().name.clone();
+        let version = pkg.prev_version().version_string.clone();
+
+        let tag = pkg.prev_tag();
+        let status = if git::tag_exists(cwd, tag)? {
+            let changed_files = pkg.changed_files()?.map(|mut changed| {
+                changed.retain(|path| path != &lock_path);
+                changed.len()
+            });
+            let mut tag_pushed = None;
+            if !opt.offline {
+                tag_pushed = Some(true);
+                for target in ws.config().push_targets() {
+                    match git::remote_tag_exists(cwd, &target.remote, tag)? {
+                        Some(true) => {}
+                        Some(false) => {
+                            tag_pushed = Some(false);
+                            break;
+                        }
+                        None => tag_pushed = None,
+                    }
+                }
+            }
+            CrateStatus {
+                name,
+                version,
+                tag: Some(tag.to_owned()),
+                commits: Some(git::commit_count_since(cwd, tag)?),
+                changed_files,
+                published: None,
+                tag_pushed,
+            }
+        } else {
+            CrateStatus {
+                name,
+                version,
+                tag: None,
+                commits: None,
+                changed_files: None,
+                published: None,
+                tag_pushed: None,
+            }
+        };
+
+        // The latest release rather than the manifest, which is usually on a development version
+        let released = status.tag.as_ref().and(pkg.prev_tag_version());
+        let published = match (released, pkg.config().registry()) {
+            (Some(released), None) => index
+                .as_ref()
+                .map(|index| cargo::is_published(index, &status.name, &released.to_string())),
+            (Some(released), Some(registry)) if !opt.offline => {
+                cargo::search_version(&status.name, registry).map(|latest| {
+                    matches!(semver::Version::parse(&latest), Ok(latest) if &latest >= released)
+                })
+            }
+            _ => None,
+        };
+        statuses.push(CrateStatus {
+            published,
+            ..status
+        });
+    }
+
+    print!("{}", render(&statuses));
+    Ok(0)
+}
+
+fn render(statuses: &[CrateStatus]) -> String {
+    fn flag(value: Option<bool>) -> String {
+        match value {
+            Some(true) => "yes".to_owned(),
+            Some(false) => "no".to_owned(),
+            None => "?".to_owned(),
+        }
+    }
+
+    let header = [
+        "CRATE",
+        "VERSION",
+        "LATEST TAG",
+        "COMMITS",
+        "CHANGED FILES",
+        "PUBLISHED",
+        "TAG PUSHED",
+    ];
+    let mut rows = vec![header.iter().map(|h| (*h).to_owned()).collect::<Vec<_>>()];
+    for status in statuses {
+        rows.push(vec![
+            status.name.clone(),
+            status.version.clone(),
+            status.tag.clone().unwrap_or_else(|| "-".to_owned()),
+            status.commits.clone().unwrap_or_else(|| "-".to_owned()),
+            status
+                .changed_files
+                .map(|c| c.to_string())
+                .unwrap_or_else(|| "-".to_owned()),
+            flag(status.published),
+            flag(status.tag_pushed),
+        ]);
+    }
+
+    let widths: Vec<usize> = (0..header.len())
+        .map(|i| rows.iter().map(|row| row[i].len()).max().unwrap_or(0))
+        .collect();
+    let mut out = String::new();
+    for row in rows {
+        let line: Vec<_> = row
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:width$}", cell, width = width))
+            .collect();
+        out.push_str(line.join("  ").trim_end());
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn render_table() {
+        let statuses = [
+            CrateStatus {
+                name: "foo".to_owned(),
+                version: "0.2.0-alpha.0".to_owned(),
+                tag: Some("foo-v0.1.0".to_owned()),
+                commits: Some("12".to_owned()),
+                changed_files: Some(3),
+                published: Some(true),
+                tag_pushed: Some(false),
+            },
+            CrateStatus {
+                name: "foo-derive".to_owned(),
+                version: "0.1.0".to_owned(),
+                tag: None,
+                commits: None,
+                changed_files: None,
+                published: None,
+                tag_pushed: None,
+            },
+        ];
+        assert_eq!(
+            render(&statuses),
+            "\
+CRATE       VERSION        LATEST TAG  COMMITS  CHANGED FILES  PUBLISHED  TAG PUSHED
+foo         0.2.0-alpha.0  foo-v0.1.0  12       3              yes        no
+foo-derive  0.1.0          -           -        -              ?          ?
+"
+        );
+    }
+}
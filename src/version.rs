@@ -1,6 +1,7 @@
 use clap::arg_enum;
 use semver::{Identifier, Version};
 
+use crate::config::DevVersionLevel;
 use crate::error::FatalError;
 
 static VERSION_ALPHA: &str = "alpha";
@@ -17,12 +18,18 @@ arg_enum! {
         Beta,
         Alpha,
         Release,
+        Snapshot,
     }
 }
 
 impl BumpLevel {
     pub fn is_pre_release(self) -> bool {
-        matches!(self, BumpLevel::Alpha | BumpLevel::Beta | BumpLevel::Rc)
+        // A snapshot is a build of the current development version, so there is no next
+        // development iteration to start.
+        matches!(
+            self,
+            BumpLevel::Alpha | BumpLevel::Beta | BumpLevel::Rc | BumpLevel::Snapshot
+        )
     }
 
     pub fn bump_version(
@@ -66,6 +73,12 @@ impl BumpLevel {
                     need_commit = true;
                 }
             }
+            BumpLevel::Snapshot => {
+                if metadata.is_none() {
+                    return Err(FatalError::MissingBuildMetadata);
+                }
+                need_commit = true;
+            }
         };
 
         if let Some(metadata) = metadata {
@@ -188,6 +201,68 @@ impl VersionExt for Version {
     }
 }
 
+/// Compute the development version that follows the release of `version`.
+///
+/// The pre-release always ends with a numeric counter (`alpha` becomes `alpha.0`) so a later
+/// `alpha`, `beta` or `rc` bump continues from it.
+pub fn dev_version(
+    version: &Version,
+    level: DevVersionLevel,
+    ext: &str,
+) -> Result<Version, FatalError> {
+    let mut pre = Version::parse(&format!("0.0.0-{}", ext))?.pre;
+    if let Some(Identifier::AlphaNumeric(_)) = pre.last() {
+        pre.push(Identifier::Numeric(0));
+    }
+
+    let mut dev = version.clone();
+    match level {
+        DevVersionLevel::Patch => dev.increment_patch(),
+        DevVersionLevel::Minor => dev.increment_minor(),
+        DevVersionLevel::Major => dev.increment_major(),
+    }
+    dev.pre = pre;
+    Ok(dev)
+}
+
+/// Whether cargo considers `next` semver compatible with `prev`.
+pub fn is_compatible(prev: &Version, next: &Version) -> bool {
+    if prev.major != next.major {
+        false
+    } else if prev.major != 0 {
+        true
+    } else if prev.minor != next.minor {
+        false
+    } else if prev.minor != 0 {
+        true
+    } else {
+        prev.patch == next.patch
+    }
+}
+
+/// The smallest bump level that makes a breaking change to `prev`.
+pub fn breaking_level(prev: &Version) -> BumpLevel {
+    if prev.major != 0 {
+        BumpLevel::Major
+    } else if prev.minor != 0 {
+        BumpLevel::Minor
+    } else {
+        BumpLevel::Patch
+    }
+}
+
+/// The version in `tag`, if it follows `tag_pattern` (a tag name with `{{version}}` left in).
+pub fn version_from_tag(tag: &str, tag_pattern: &str) -> Option<Version> {
+    const PLACEHOLDER: &str = "{{version}}";
+    let index = tag_pattern.find(PLACEHOLDER)?;
+    let before = &tag_pattern[..index];
+    let after = &tag_pattern[index + PLACEHOLDER.len()..];
+    if tag.len() < before.len() + after.len() || !tag.starts_with(before) || !tag.ends_with(after) {
+        return None;
+    }
+    Version::parse(&tag[before.len()..tag.len() - after.len()]).ok()
+}
+
 pub fn set_requirement(
     req: &semver::VersionReq,
     version: &semver::Version,
@@ -452,6 +527,117 @@ mod test {
             let _ = v.metadata("git.123456");
             assert_eq!(v, Version::parse("1.0.0+git.123456").unwrap());
         }
+
+        #[test]
+        fn snapshot() {
+            let mut v = Version::parse("1.4.0-dev").unwrap();
+            let metadata = "g1a2b3c4".to_owned();
+            assert!(BumpLevel::Snapshot
+                .bump_version(&mut v, Some(&metadata))
+                .unwrap());
+            assert_eq!(v.to_string(), "1.4.0-dev+g1a2b3c4");
+
+            let mut v2 = Version::parse("1.4.0-dev").unwrap();
+            assert!(BumpLevel::Snapshot.bump_version(&mut v2, None).is_err());
+        }
+    }
+
+    mod dev_version {
+        use super::*;
+
+        #[test]
+        fn level() {
+            let v = Version::parse("1.3.0").unwrap();
+            let patch = dev_version(&v, DevVersionLevel::Patch, "alpha.0").unwrap();
+            assert_eq!(patch, Version::parse("1.3.1-alpha.0").unwrap());
+            let minor = dev_version(&v, DevVersionLevel::Minor, "alpha.0").unwrap();
+            assert_eq!(minor, Version::parse("1.4.0-alpha.0").unwrap());
+            let major = dev_version(&v, DevVersionLevel::Major, "alpha.0").unwrap();
+            assert_eq!(major, Version::parse("2.0.0-alpha.0").unwrap());
+        }
+
+        #[test]
+        fn counter() {
+            let v = Version::parse("1.3.0+g1a2b3c4").unwrap();
+            let mut dev = dev_version(&v, DevVersionLevel::Minor, "alpha").unwrap();
+            assert_eq!(dev.to_string(), "1.4.0-alpha.0");
+
+            dev.increment_alpha().unwrap();
+            assert_eq!(dev, Version::parse("1.4.0-alpha.1").unwrap());
+
+            let mut dev = dev_version(&v, DevVersionLevel::Minor, "alpha.0").unwrap();
+            dev.increment_beta().unwrap();
+            assert_eq!(dev, Version::parse("1.4.0-beta.1").unwrap());
+
+            assert!(dev_version(&v, DevVersionLevel::Patch, "not valid").is_err());
+        }
+    }
+
+    mod version_from_tag {
+        use super::*;
+
+        #[test]
+        fn matches() {
+            let pattern = "foo-v{{version}}";
+            assert_eq!(
+                version_from_tag("foo-v1.2.3", pattern),
+                Some(Version::parse("1.2.3").unwrap())
+            );
+            assert_eq!(
+                version_from_tag("foo-v0.1.0-alpha.1", pattern),
+                Some(Version::parse("0.1.0-alpha.1").unwrap())
+            );
+            assert_eq!(version_from_tag("foo-bar-v1.2.3", pattern), None);
+            assert_eq!(version_from_tag("foo-v", pattern), None);
+            assert_eq!(version_from_tag("v1.2.3", pattern), None);
+        }
+
+        #[test]
+        fn no_version() {
+            assert_eq!(version_from_tag("latest", "latest"), None);
+        }
+    }
+
+    mod compatible {
+        use super::*;
+
+        fn assert_compatible(prev: &str, next: &str, expected: bool) {
+            let prev = Version::parse(prev).unwrap();
+            let next = Version::parse(next).unwrap();
+            assert_eq!(
+                is_compatible(&prev, &next),
+                expected,
+                "{} -> {}",
+                prev,
+                next
+            );
+        }
+
+        #[test]
+        fn stable() {
+            assert_compatible("1.2.3", "1.2.4", true);
+            assert_compatible("1.2.3", "1.3.0", true);
+            assert_compatible("1.2.3", "2.0.0", false);
+            assert_eq!(
+                breaking_level(&Version::parse("1.2.3").unwrap()).to_string(),
+                "Major"
+            );
+        }
+
+        #[test]
+        fn unstable() {
+            assert_compatible("0.2.3", "0.2.4", true);
+            assert_compatible("0.2.3", "0.3.0", false);
+            assert_compatible("0.0.3", "0.0.4", false);
+            assert_eq!(
+                breaking_level(&Version::parse("0.2.3").unwrap()).to_string(),
+                "Minor"
+            );
+            assert_eq!(
+                breaking_level(&Version::parse("0.0.3").unwrap()).to_string(),
+                "Patch"
+            );
+        }
     }
 
     mod set_requirement {